@@ -0,0 +1,735 @@
+//! Conversion of images to ASCII art, shared by the image2ascii binary and usable on its own
+
+use crossterm::style::{Color, Stylize};
+use image::{DynamicImage, GenericImageView};
+use rayon::prelude::*;
+use unicode_width::UnicodeWidthChar;
+
+const HEAT_MAP_LENGTH: usize = 16;
+/// Bit of the dot for each (column, row) in a 2x4 Braille cell
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+/// Levels of each channel in xterm's 6x6x6 color cube
+const ANSI_CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+/// The standard 16 ANSI colors with their usual xterm RGB values
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+const HEAT_MAP: [char; HEAT_MAP_LENGTH] = [
+    ' ', '.', '´', ':', '~', '+', 'i', 'x', '!', 'I', '#', '$', 'X', '▄', '■', '█',
+];
+/// Default ramp restricted to printable ASCII, for fonts and channels without the block glyphs
+const ASCII_HEAT_MAP: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ColorMode {
+    /// 24-bit RGB
+    #[cfg_attr(feature = "clap", value(name = "truecolor"))]
+    TrueColor,
+    /// xterm's 6x6x6 color cube plus the grayscale ramp
+    Ansi256,
+    /// The 16 standard ANSI colors
+    Ansi16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum LumaMode {
+    /// Plain average of the three channels
+    Average,
+    /// Rec. 601 weights of 0.299, 0.587 and 0.114
+    Rec601,
+    /// Rec. 709 weights of 0.2126, 0.7152 and 0.0722
+    Rec709,
+}
+
+/// Settings that control how an image is turned into ASCII art
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Color every cell with its pixel's color
+    pub colored: bool,
+    /// Use the block character for every cell, so only color carries the image
+    pub block_character: bool,
+    /// How many times every glyph is repeated across a cell
+    pub cell_width: usize,
+    /// Brightness ramp from darkest to brightest, in place of the default one
+    pub charset: Option<String>,
+    /// Give bright pixels sparse glyphs and dark pixels dense ones
+    pub invert: bool,
+    /// Formula pixel brightness is worked out with when picking glyphs
+    pub luma: LumaMode,
+    /// Palette colored output is quantized to
+    pub color_mode: ColorMode,
+    /// Brightness offset, as a fraction of full brightness
+    pub brightness: f32,
+    /// Contrast multiplier
+    pub contrast: f32,
+    /// Stretch the image's luma range over the full range before any other adjustment
+    pub auto_contrast: bool,
+    /// Gamma correction, where values above 1 brighten midtones
+    pub gamma: f32,
+    /// Number of levels every color channel is snapped to. Glyphs are still picked from the
+    /// unposterized pixels.
+    pub posterize: Option<u8>,
+    /// Colors every output color is snapped to the nearest of. Like posterizing, this leaves
+    /// the glyphs alone.
+    pub palette: Option<Vec<(u8, u8, u8)>>,
+    /// Pick glyphs by edge strength rather than brightness
+    pub edges: bool,
+    /// Dither with Floyd–Steinberg error diffusion before picking glyphs
+    pub dither: bool,
+    /// Pack two pixels into every cell with colored half blocks
+    pub half_block: bool,
+    /// Background color of every cell
+    pub background: Option<(u8, u8, u8)>,
+    /// Fall back to a ramp of printable ASCII only, rather than one with block glyphs
+    pub ascii_only: bool,
+    /// Draw pixels with luma above this as full cells and the rest as blank ones, in place of the
+    /// brightness ramp
+    pub threshold: Option<u8>,
+    /// Pack 2x4 pixels into every cell as Braille dots
+    pub braille: bool,
+    /// Luma above which a pixel's Braille dot is raised
+    pub braille_threshold: u8,
+    /// Blank columns left after every cell
+    pub cell_spacing: usize,
+    /// Replace every color with its photographic negative, before glyphs are picked
+    pub negate: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            colored: false,
+            block_character: false,
+            cell_width: 3,
+            charset: None,
+            invert: false,
+            luma: LumaMode::Average,
+            color_mode: ColorMode::TrueColor,
+            brightness: 0.0,
+            contrast: 1.0,
+            auto_contrast: false,
+            gamma: 1.0,
+            posterize: None,
+            palette: None,
+            edges: false,
+            dither: false,
+            half_block: false,
+            background: None,
+            ascii_only: false,
+            threshold: None,
+            braille: false,
+            braille_threshold: 128,
+            cell_spacing: 0,
+            negate: false,
+        }
+    }
+}
+
+/// Glyphs to map brightness onto, from darkest to brightest. Every glyph is repeated
+/// `cell_width` times, padded out to the widest glyph's columns and followed by `cell_spacing`
+/// spaces. Block character mode has a single glyph, since color carries the image.
+pub fn heat_map(opts: &RenderOptions) -> Vec<String> {
+    let width = glyph_width(opts);
+    ramp(opts)
+        .into_iter()
+        .map(|c| {
+            let padding = (width - char_width(c)) * opts.cell_width + opts.cell_spacing;
+            c.to_string().repeat(opts.cell_width) + &" ".repeat(padding)
+        })
+        .collect()
+}
+
+/// Terminal columns the widest glyph of the heat map takes up, 2 for most CJK characters and
+/// emoji
+pub fn glyph_width(opts: &RenderOptions) -> usize {
+    ramp(opts).into_iter().map(char_width).max().unwrap_or(1)
+}
+
+/// Terminal columns a glyph takes up. Zero width and control characters still get a column,
+/// since they're drawn as cells all the same.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(1).max(1)
+}
+
+/// The characters of the heat map, from darkest to brightest
+fn ramp(opts: &RenderOptions) -> Vec<char> {
+    if opts.block_character {
+        vec!['█']
+    } else if opts.threshold.is_some() {
+        vec![' ', if opts.ascii_only { '@' } else { '█' }]
+    } else {
+        match &opts.charset {
+            Some(charset) if !charset.is_empty() => charset.chars().collect(),
+            _ if opts.ascii_only => ASCII_HEAT_MAP.to_vec(),
+            _ => HEAT_MAP.to_vec(),
+        }
+    }
+}
+
+/// The image's pixels, negated if asked for, with their luma range stretched over 0 to 255 in
+/// auto contrast mode. The range runs from the 1st to the 99th percentile, so a few outliers
+/// don't hold it back.
+fn source_pixels(img: &DynamicImage, opts: &RenderOptions) -> image::RgbImage {
+    let mut rgb = img.to_rgb8();
+    if opts.negate {
+        image::imageops::invert(&mut rgb);
+    }
+    if !opts.auto_contrast {
+        return rgb;
+    }
+    let mut histogram = [0usize; 256];
+    for p in rgb.pixels() {
+        histogram[luma(&p.0) as usize] += 1;
+    }
+    let total: usize = histogram.iter().sum();
+    let percentile = |fraction: f32| {
+        let target = (total as f32 * fraction) as usize;
+        let mut seen = 0;
+        histogram
+            .iter()
+            .position(|count| {
+                seen += count;
+                seen > target
+            })
+            .unwrap_or(255) as f32
+    };
+    let (low, high) = (percentile(0.01), percentile(0.99));
+    if high <= low {
+        return rgb;
+    }
+    for p in rgb.pixels_mut() {
+        p.0 =
+            p.0.map(|c| ((c as f32 - low) * 255.0 / (high - low)).clamp(0.0, 255.0) as u8);
+    }
+    rgb
+}
+
+/// Apply the brightness, contrast and gamma adjustments to a pixel
+fn adjust_pixel(p: [u8; 3], opts: &RenderOptions) -> [u8; 3] {
+    if opts.brightness == 0.0 && opts.contrast == 1.0 && opts.gamma == 1.0 {
+        return p;
+    }
+    p.map(|c| {
+        let c = ((c as f32 - 128.0) * opts.contrast + 128.0 + opts.brightness * 255.0)
+            .clamp(0.0, 255.0);
+        if opts.gamma == 1.0 {
+            c as u8
+        } else {
+            ((c / 255.0).powf(1.0 / opts.gamma) * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        }
+    })
+}
+
+/// Rec. 601 luma of a pixel
+fn luma(p: &[u8]) -> u8 {
+    (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32).round() as u8
+}
+
+fn brightness(p: &[u8], opts: &RenderOptions) -> u8 {
+    match opts.luma {
+        LumaMode::Average => ((p[0] as u32 + p[1] as u32 + p[2] as u32) / 3) as u8,
+        LumaMode::Rec601 => luma(p),
+        LumaMode::Rec709 => {
+            (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32).round() as u8
+        }
+    }
+}
+
+/// Gradient magnitude of every pixel in row-major order, from a Sobel operator run over the
+/// image's luma. Neighbours past the border are clamped to the nearest edge pixel.
+fn edge_detect(img: &DynamicImage) -> Vec<u8> {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        luma.get_pixel(x, y)[0] as i32
+    };
+    let mut gradients = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = at(x + 1, y - 1) + 2 * at(x + 1, y) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2 * at(x - 1, y)
+                - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2 * at(x, y + 1) + at(x + 1, y + 1)
+                - at(x - 1, y - 1)
+                - 2 * at(x, y - 1)
+                - at(x + 1, y - 1);
+            gradients.push(((gx * gx + gy * gy) as f32).sqrt().min(255.0) as u8);
+        }
+    }
+    gradients
+}
+
+/// Bucket a brightness (or edge strength) into one of `len` heat map entries.
+/// The result is clamped so that pure white never indexes past the end of the heat map.
+fn heat_map_index(brightness: u8, len: usize) -> usize {
+    (brightness as usize * len / 256).min(len - 1)
+}
+
+/// Bucket values into `len` heat map entries with Floyd–Steinberg error diffusion, so smooth
+/// gradients come out as a mix of neighbouring glyphs instead of visible bands
+fn dither(values: &[u8], width: u32, len: usize) -> Vec<usize> {
+    let width = width as usize;
+    let bucket_size = 256.0 / len as f32;
+    let mut values: Vec<f32> = values.iter().map(|value| *value as f32).collect();
+    let mut indices = Vec::with_capacity(values.len());
+    for i in 0..values.len() {
+        let value = values[i].clamp(0.0, 255.0);
+        let index = heat_map_index(value as u8, len);
+        let error = value - (index as f32 + 0.5) * bucket_size;
+        let x = i % width;
+        if x + 1 < width {
+            values[i + 1] += error * 7.0 / 16.0;
+        }
+        if i + width < values.len() {
+            if x > 0 {
+                values[i + width - 1] += error * 3.0 / 16.0;
+            }
+            values[i + width] += error * 5.0 / 16.0;
+            if x + 1 < width {
+                values[i + width + 1] += error / 16.0;
+            }
+        }
+        indices.push(index);
+    }
+    indices
+}
+
+/// Snap every channel to one of `opts.posterize` evenly spaced levels
+fn posterize(p: [u8; 3], opts: &RenderOptions) -> [u8; 3] {
+    match opts.posterize {
+        Some(levels) if levels >= 2 => {
+            let step = 255.0 / (levels - 1) as f32;
+            p.map(|c| ((c as f32 / step).round() * step).min(255.0) as u8)
+        }
+        _ => p,
+    }
+}
+
+/// Snap a color to the nearest entry of `opts.palette`
+fn snap_to_palette(p: [u8; 3], opts: &RenderOptions) -> [u8; 3] {
+    match &opts.palette {
+        Some(palette) => palette
+            .iter()
+            .min_by_key(|entry| color_distance((p[0], p[1], p[2]), **entry))
+            .map_or(p, |(r, g, b)| [*r, *g, *b]),
+        None => p,
+    }
+}
+
+/// The color a pixel is drawn in, once posterized and snapped to the palette
+fn output_color(p: [u8; 3], opts: &RenderOptions) -> [u8; 3] {
+    snap_to_palette(posterize(p, opts), opts)
+}
+
+/// The color of every pixel in row-major order, along with the index of its glyph in `heat_map`
+pub fn pixels_with_value(
+    img: &DynamicImage,
+    heat_map: &[String],
+    opts: &RenderOptions,
+) -> Vec<(u8, u8, u8, usize)> {
+    let pixels: Vec<[u8; 3]> = source_pixels(img, opts)
+        .as_raw()
+        .par_chunks_exact(3)
+        .map(|p| adjust_pixel([p[0], p[1], p[2]], opts))
+        .collect();
+    let values = if opts.edges {
+        edge_detect(img)
+    } else if opts.threshold.is_some() {
+        pixels.par_iter().map(|p| luma(p)).collect()
+    } else {
+        pixels.par_iter().map(|p| brightness(p, opts)).collect()
+    };
+    let indices = if let Some(threshold) = opts.threshold {
+        values
+            .par_iter()
+            .map(|value| usize::from(*value > threshold))
+            .collect()
+    } else if opts.dither {
+        dither(&values, img.width(), heat_map.len())
+    } else {
+        values
+            .par_iter()
+            .map(|value| heat_map_index(*value, heat_map.len()))
+            .collect()
+    };
+    pixels
+        .par_iter()
+        .zip(indices)
+        .map(|(p, value)| {
+            let value = if opts.invert {
+                heat_map.len() - 1 - value
+            } else {
+                value
+            };
+            let [r, g, b] = output_color(*p, opts);
+            (r, g, b, value)
+        })
+        .collect()
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn ansi_cube_index(c: u8) -> usize {
+    match c {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (c as usize - 35) / 40,
+    }
+}
+
+/// Quantize an RGB value to the palette supported by the chosen color mode
+pub fn terminal_color(rgb: (u8, u8, u8), mode: ColorMode) -> Color {
+    match mode {
+        ColorMode::TrueColor => Color::Rgb {
+            r: rgb.0,
+            g: rgb.1,
+            b: rgb.2,
+        },
+        ColorMode::Ansi256 => {
+            let (r, g, b) = (
+                ansi_cube_index(rgb.0),
+                ansi_cube_index(rgb.1),
+                ansi_cube_index(rgb.2),
+            );
+            let cube = (
+                ANSI_CUBE_LEVELS[r],
+                ANSI_CUBE_LEVELS[g],
+                ANSI_CUBE_LEVELS[b],
+            );
+            // The grayscale ramp runs from 8 to 238 in steps of 10
+            let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+            let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+            let gray = 8 + 10 * gray_index;
+            if color_distance(rgb, (gray, gray, gray)) < color_distance(rgb, cube) {
+                Color::AnsiValue(232 + gray_index)
+            } else {
+                Color::AnsiValue((16 + 36 * r + 6 * g + b) as u8)
+            }
+        }
+        ColorMode::Ansi16 => {
+            ANSI_16
+                .iter()
+                .min_by_key(|(_, value)| color_distance(rgb, *value))
+                .unwrap()
+                .0
+        }
+    }
+}
+
+/// The text drawn for a pixel returned by `pixels_with_value`
+pub fn glyph<'a>(p: &(u8, u8, u8, usize), heat_map: &'a [String]) -> &'a str {
+    heat_map[p.3].as_str()
+}
+
+/// Two vertically adjacent pixels per cell, as an upper half block colored with the top pixel
+/// on a background of the bottom pixel. Without color, the halves are shades of gray.
+fn half_block_rows(
+    pixels_with_value: &[(u8, u8, u8, usize)],
+    width: u32,
+    opts: &RenderOptions,
+) -> Vec<String> {
+    let rows: Vec<_> = pixels_with_value.chunks(width.max(1) as usize).collect();
+    let half_block = "▀".repeat(opts.cell_width);
+    let spacing = " ".repeat(opts.cell_spacing);
+    let color = |p: &(u8, u8, u8, usize)| {
+        let rgb = if opts.colored {
+            (p.0, p.1, p.2)
+        } else {
+            let gray = brightness(&[p.0, p.1, p.2], opts);
+            (gray, gray, gray)
+        };
+        terminal_color(rgb, opts.color_mode)
+    };
+    rows.chunks(2)
+        .map(|pair| {
+            let mut line = String::new();
+            for (x, top) in pair[0].iter().enumerate() {
+                let text = half_block.as_str().with(color(top));
+                let text = match pair.get(1) {
+                    Some(bottom) => text.on(color(&bottom[x])),
+                    None => text,
+                };
+                line.push_str(&text.to_string());
+                push_run(&mut line, &spacing, None, opts);
+            }
+            line
+        })
+        .collect()
+}
+
+/// Every 2x4 block of pixels as a Braille character, with a dot raised for every pixel brighter
+/// than the threshold. Colored cells take the average color of their block.
+fn braille_rows(img: &DynamicImage, opts: &RenderOptions) -> Vec<String> {
+    let rgb = source_pixels(img, opts);
+    let (width, height) = rgb.dimensions();
+    let spacing = " ".repeat(opts.cell_spacing);
+    (0..height.div_ceil(4))
+        .map(|cell_y| {
+            let mut line = String::new();
+            for cell_x in 0..width.div_ceil(2) {
+                let mut bits = 0;
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+                for (dx, column) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dy, bit) in column.iter().enumerate() {
+                        let (x, y) = (cell_x * 2 + dx as u32, cell_y * 4 + dy as u32);
+                        if x >= width || y >= height {
+                            continue;
+                        }
+                        let p = adjust_pixel(rgb.get_pixel(x, y).0, opts);
+                        if (luma(&p) > opts.braille_threshold) != opts.invert {
+                            bits |= bit;
+                        }
+                        for (total, c) in sum.iter_mut().zip(p) {
+                            *total += c as u32;
+                        }
+                        count += 1;
+                    }
+                }
+                let text = char::from_u32(0x2800 + bits).unwrap_or(' ').to_string();
+                let mut text = text.stylize();
+                if opts.colored {
+                    let [r, g, b] =
+                        output_color(sum.map(|total| (total / count.max(1)) as u8), opts);
+                    text = text.with(terminal_color((r, g, b), opts.color_mode));
+                }
+                if let Some(background) = opts.background {
+                    text = text.on(terminal_color(background, opts.color_mode));
+                }
+                line.push_str(&text.to_string());
+                push_run(&mut line, &spacing, None, opts);
+            }
+            line
+        })
+        .collect()
+}
+
+/// Render an image into rows of ASCII art, one per line of output and without line endings.
+/// Colored rows carry their ANSI escape sequences.
+pub fn ascii_rows(img: &DynamicImage, opts: &RenderOptions) -> Vec<String> {
+    if opts.braille {
+        return braille_rows(img, opts);
+    }
+    let heat_map = heat_map(opts);
+    let pixels_with_value = pixels_with_value(img, &heat_map, opts);
+    if opts.half_block {
+        return half_block_rows(&pixels_with_value, img.width(), opts);
+    }
+    pixels_with_value
+        .chunks(img.width().max(1) as usize)
+        .map(|row| {
+            // Runs of cells sharing a color are styled once, to keep escape sequences down
+            let mut line = String::new();
+            let mut run = String::new();
+            let mut run_color = None;
+            for p in row {
+                let text = glyph(p, &heat_map);
+                // Blank cells show no foreground, so they can join whatever run they're in
+                let color = if text.trim().is_empty() {
+                    run_color
+                } else if opts.colored {
+                    Some(terminal_color((p.0, p.1, p.2), opts.color_mode))
+                } else {
+                    None
+                };
+                if color != run_color {
+                    push_run(&mut line, &run, run_color, opts);
+                    run.clear();
+                    run_color = color;
+                }
+                run.push_str(text);
+            }
+            push_run(&mut line, &run, run_color, opts);
+            line
+        })
+        .collect()
+}
+
+/// Append a run of cells to a line, styled with their shared color and the background
+fn push_run(line: &mut String, run: &str, color: Option<Color>, opts: &RenderOptions) {
+    if run.is_empty() {
+        return;
+    }
+    if color.is_none() && opts.background.is_none() {
+        line.push_str(run);
+        return;
+    }
+    let mut text = run.stylize();
+    if let Some(color) = color {
+        text = text.with(color);
+    }
+    if let Some(background) = opts.background {
+        text = text.on(terminal_color(background, opts.color_mode));
+    }
+    line.push_str(&text.to_string());
+}
+
+/// Render an image as ASCII art, with a newline after every row
+pub fn image_to_ascii(img: &DynamicImage, opts: &RenderOptions) -> String {
+    ascii_rows(img, opts)
+        .into_iter()
+        .map(|row| row + "\n")
+        .collect()
+}
+
+fn push_sixel_run(out: &mut String, sixel: char, count: usize) {
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, sixel));
+    } else {
+        (0..count).for_each(|_| out.push(sixel));
+    }
+}
+
+/// Encode an image as a Sixel escape sequence, with colors quantized to xterm's 6x6x6 cube
+pub fn sixel(img: &image::RgbImage) -> String {
+    let (width, height) = img.dimensions();
+    let color = |x: u32, y: u32| {
+        let p = img.get_pixel(x, y);
+        36 * ansi_cube_index(p[0]) + 6 * ansi_cube_index(p[1]) + ansi_cube_index(p[2])
+    };
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        // Sixel palette channels are percentages
+        let level = |i: usize| ANSI_CUBE_LEVELS[i] as u32 * 100 / 255;
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            index,
+            level(index / 36),
+            level(index / 6 % 6),
+            level(index % 6)
+        ));
+    }
+    // Every sixel covers a column of 6 pixels, so draw the image in bands of 6 rows, one pass
+    // per color present in the band
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used = [false; 216];
+        for y in rows.clone() {
+            for x in 0..width {
+                used[color(x, y)] = true;
+            }
+        }
+        for index in (0..216).filter(|index| used[*index]) {
+            out.push_str(&format!("#{}", index));
+            let mut run = ('?', 0);
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|y| color(x, *y) == index)
+                    .fold(0, |bits, y| bits | 1 << (y - band));
+                let column = (63 + bits) as u8 as char;
+                if column == run.0 {
+                    run.1 += 1;
+                } else {
+                    push_sixel_run(&mut out, run.0, run.1);
+                    run = (column, 1);
+                }
+            }
+            push_sixel_run(&mut out, run.0, run.1);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Encode an image as a kitty graphics protocol escape sequence, carrying it as PNG. With
+/// `cells`, the terminal scales it to cover that many columns and rows.
+pub fn kitty(img: &image::RgbImage, cells: Option<(u32, u32)>) -> image::ImageResult<String> {
+    let data = base64_png(img)?;
+    let mut control = String::from("a=T,f=100");
+    if let Some((columns, rows)) = cells {
+        control.push_str(&format!(",c={},r={}", columns, rows));
+    }
+    // Payloads are sent in chunks of at most 4096 bytes, each saying if more follow
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = usize::from(index + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if index == 0 {
+            out.push_str(&format!("\x1b_G{},m={};{}\x1b\\", control, more, chunk));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    Ok(out)
+}
+
+/// Encode an image as an iTerm2 inline image escape sequence, carrying it as PNG. With
+/// `cells`, the terminal stretches it over that many columns and rows.
+pub fn iterm(img: &image::RgbImage, cells: Option<(u32, u32)>) -> image::ImageResult<String> {
+    let data = base64_png(img)?;
+    let size = match cells {
+        Some((columns, rows)) => {
+            format!(";width={};height={};preserveAspectRatio=0", columns, rows)
+        }
+        None => String::new(),
+    };
+    Ok(format!("\x1b]1337;File=inline=1{}:{}\x07", size, data))
+}
+
+/// An image as base64 encoded PNG, the way terminal graphics protocols take it
+fn base64_png(img: &image::RgbImage) -> image::ImageResult<String> {
+    use base64::Engine;
+    use image::ImageEncoder;
+
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png).write_image(
+        img.as_raw(),
+        img.width(),
+        img.height(),
+        image::ColorType::Rgb8,
+    )?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_stays_in_the_heat_map() {
+        assert_eq!(heat_map_index(255, HEAT_MAP_LENGTH), HEAT_MAP_LENGTH - 1);
+        assert_eq!(heat_map_index(0, HEAT_MAP_LENGTH), 0);
+    }
+
+    #[test]
+    fn white_pixel_gets_the_brightest_glyph() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            1,
+            1,
+            image::Rgb([255, 255, 255]),
+        ));
+        let opts = RenderOptions::default();
+        let heat_map = heat_map(&opts);
+        let pixels = pixels_with_value(&img, &heat_map, &opts);
+        assert_eq!(pixels, vec![(255, 255, 255, heat_map.len() - 1)]);
+    }
+}
@@ -1,22 +1,37 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use crossterm::{
-    cursor, execute, queue,
-    style::{Color, Print, PrintStyledContent, Stylize},
+    cursor,
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute, queue,
+    style::{Print, PrintStyledContent, Stylize},
     terminal::{self, Clear, ClearType},
     ExecutableCommand,
 };
 use file_format::FileFormat;
 use image::buffer::ConvertBuffer;
 use image::{
-    codecs::gif::GifDecoder, imageops::FilterType, io::Reader as ImageReader, AnimationDecoder,
-    DynamicImage, Frame, GenericImageView, Pixel,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    imageops::FilterType,
+    io::Reader as ImageReader,
+    AnimationDecoder, DynamicImage, Frame, GenericImageView,
+};
+use image2ascii::{
+    ascii_rows, glyph, glyph_width, heat_map, image_to_ascii, iterm, kitty, pixels_with_value,
+    sixel, terminal_color, ColorMode, LumaMode, RenderOptions,
 };
 use std::{
-    io::{stdout, Write},
+    collections::VecDeque,
+    fs::File,
+    io::{stdin, stdout, BufWriter, IsTerminal, Read, Write},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 struct EventManager<'a> {
@@ -33,12 +48,16 @@ impl<'a> EventManager<'a> {
         self
     }
 
-    pub fn run(&mut self) {
+    /// Run the callbacks of the events that fired, returning whether any did
+    pub fn run(&mut self) -> bool {
+        let mut fired = false;
         for e in &mut self.events {
             if e.0() {
                 e.1();
+                fired = true;
             }
         }
+        fired
     }
 }
 
@@ -48,13 +67,54 @@ impl<'a> Default for EventManager<'a> {
     }
 }
 
+/// Keeps raw mode on, optionally in the alternate screen, for as long as it's alive. Dropping it
+/// restores the terminal, including when a stream errors out. The screen is switched through
+/// `out`, which is written to through the guard meanwhile.
+struct TerminalGuard<W: Write> {
+    out: W,
+    raw_mode: bool,
+    alternate_screen: bool,
+}
+
+impl<W: Write> TerminalGuard<W> {
+    fn new(mut out: W, alternate_screen: bool) -> Result<Self> {
+        if alternate_screen {
+            execute!(out, terminal::EnterAlternateScreen)?;
+        }
+        // Raw mode lets keypresses through without waiting for Enter
+        let raw_mode = terminal::enable_raw_mode().is_ok();
+        Ok(TerminalGuard {
+            out,
+            raw_mode,
+            alternate_screen,
+        })
+    }
+}
+
+impl<W: Write> Drop for TerminalGuard<W> {
+    fn drop(&mut self) {
+        if self.raw_mode {
+            let _ = terminal::disable_raw_mode();
+        }
+        if self.alternate_screen {
+            let _ = execute!(self.out, terminal::LeaveAlternateScreen);
+        }
+    }
+}
+
 /// Simple program that generates ASCII art from an input image
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Path of the image file to be asii art'd
-    #[arg(short, long)]
-    file_path: String,
+    /// Path of the image file to be asii art'd. Use - to read it from stdin, or a directory to show
+    /// its images as a slideshow. Several paths are shown one after another
+    #[arg(
+        short,
+        long,
+        num_args = 1..,
+        required_unless_present_any = ["webcam_feed", "clipboard", "input_list"]
+    )]
+    file_path: Vec<String>,
 
     /// Colorize the ascii output
     #[arg(short, long, default_value_t = false)]
@@ -64,11 +124,13 @@ struct Args {
     #[arg(short, long, default_value_t = false)]
     resize: bool,
 
-    /// Time to wait between frames (GIF mode)
-    #[arg(short, long, default_value_t = 200)]
-    animation_delay: u64,
+    /// Time in milliseconds to wait between frames. GIFs default to their own frame timing,
+    /// everything else to 200ms
+    #[arg(short, long, conflicts_with = "fps")]
+    animation_delay: Option<u64>,
 
-    /// Loop animation (stream mode). Will enable video for the webcam_feed option.
+    /// Loop animation (stream mode). GIFs loop as many times as they declare.
+    /// Will enable video for the webcam_feed option.
     #[arg(short, long, default_value_t = false)]
     loop_animation: bool,
 
@@ -79,225 +141,2633 @@ struct Args {
     /// Display ASCII'd frame from your webcam feed
     #[arg(short, long, default_value_t = false)]
     webcam_feed: bool,
+
+    /// Custom brightness ramp, from darkest to brightest (e.g. " .:-=+*#%@")
+    #[arg(long, value_parser = parse_charset)]
+    charset: Option<String>,
+
+    /// Invert the brightness ramp (bright pixels get sparse glyphs), for dark terminal backgrounds
+    #[arg(short, long, default_value_t = false)]
+    invert: bool,
+
+    /// Index of the camera device to use for the webcam_feed option
+    #[arg(long, default_value_t = 0)]
+    camera_index: u32,
+
+    /// Resize the image to this many cells wide. Preserves aspect ratio if --height isn't given.
+    /// Takes precedence over --resize
+    #[arg(long, value_parser = parse_positive::<u32>)]
+    width: Option<u32>,
+
+    /// Resize the image to this many cells high. Preserves aspect ratio if --width isn't given.
+    /// Takes precedence over --resize
+    #[arg(long, value_parser = parse_positive::<u32>)]
+    height: Option<u32>,
+
+    /// Write the plain (uncolored) ASCII art to this file instead of the terminal.
+    /// A path ending in .html produces an HTML document, colored if --colored is set, and one
+    /// ending in .json a grid of cells with their glyphs and colors. A path ending in .svg
+    /// produces a scalable image, and one ending in .ans the art with its ANSI colors for `cat`.
+    /// Streams into a named pipe write every frame, ending with --frame-separator
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Width:height ratio of a single terminal character cell in your font, used to keep
+    /// resized images from looking squashed or stretched
+    #[arg(long, default_value_t = 0.5, value_parser = parse_positive::<f32>)]
+    cell_aspect: f32,
+
+    /// Grayscale output: never emit color, and bucket brightness by Rec. 601 luma unless
+    /// --luma picks another formula
+    #[arg(long, default_value_t = false)]
+    grayscale: bool,
+
+    /// Color palette to quantize --colored output to, for terminals without 24-bit color
+    #[arg(long, value_enum, default_value_t = ColorMode::TrueColor)]
+    color_mode: ColorMode,
+
+    /// Frames per second to play streams at. Alternative to --animation-delay, the two are
+    /// mutually exclusive
+    #[arg(long, value_parser = parse_fps)]
+    fps: Option<f64>,
+
+    /// Keep the last frame of a stream on screen until q, Esc or Ctrl-C is pressed
+    #[arg(long, default_value_t = false)]
+    hold_last_frame: bool,
+
+    /// Brightness offset, as a fraction of full brightness (e.g. 0.2 or -0.1)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    brightness: f32,
+
+    /// Contrast multiplier, 1.0 leaves contrast unchanged
+    #[arg(long, default_value_t = 1.0)]
+    contrast: f32,
+
+    /// Trace edges: pick glyphs by Sobel gradient magnitude instead of brightness
+    #[arg(long, default_value_t = false)]
+    edges: bool,
+
+    /// Dither with Floyd–Steinberg error diffusion before picking glyphs, to smooth out banding
+    #[arg(long, default_value_t = false)]
+    dither: bool,
+
+    /// Filter used when resizing. Nearest is the fastest, the others look smoother
+    #[arg(long, value_enum, default_value_t = FilterArg::Nearest)]
+    filter: FilterArg,
+
+    /// Render two pixels per cell using upper half blocks, doubling vertical resolution. They're
+    /// in color with --colored and in shades of gray otherwise. Applies to terminal output only
+    #[arg(long, default_value_t = false)]
+    half_block: bool,
+
+    /// Draw the actual image with Sixel graphics instead of ASCII, on terminals that support it.
+    /// Its pixels are drawn one to one, so --resize is ignored and --width and --height are in
+    /// pixels
+    #[arg(long, default_value_t = false)]
+    sixel: bool,
+
+    /// Draw the actual image with the kitty graphics protocol instead of ASCII, on kitty and
+    /// terminals compatible with it
+    #[arg(long, default_value_t = false, conflicts_with = "sixel")]
+    kitty: bool,
+
+    /// Draw the actual image with iTerm2's inline image protocol instead of ASCII
+    #[arg(long, default_value_t = false, conflicts_with_all = ["sixel", "kitty"])]
+    iterm: bool,
+
+    /// Stop streams (webcam, video, GIF) after this many frames
+    #[arg(long)]
+    frames: Option<usize>,
+
+    /// Center the image in the terminal when it's smaller than the viewport
+    #[arg(long, default_value_t = false)]
+    center: bool,
+
+    /// Background color for every cell, as #rrggbb. Has no visible effect with
+    /// --block-character, since blocks fill the whole cell
+    #[arg(long, value_parser = parse_hex_color)]
+    background: Option<(u8, u8, u8)>,
+
+    /// Print every frame below the last one instead of drawing over it, so output scrolls
+    #[arg(long, default_value_t = false)]
+    no_clear: bool,
+
+    /// Start videos this far in, as HH:MM:SS, MM:SS or seconds
+    #[arg(long, value_parser = parse_timestamp)]
+    start: Option<Duration>,
+
+    /// Only play this much of a video, as HH:MM:SS, MM:SS or seconds
+    #[arg(long, value_parser = parse_timestamp)]
+    duration: Option<Duration>,
+
+    /// Only draw with printable ASCII, swapping the default ramp for one without block glyphs.
+    /// A --charset still takes precedence
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["block_character", "half_block", "braille"]
+    )]
+    ascii_only: bool,
+
+    /// Print the size the output would be rendered at to stderr, without rendering it
+    #[arg(long, default_value_t = false)]
+    print_size: bool,
+
+    /// Draw with Braille characters, packing 2x4 pixels into every cell
+    #[arg(long, default_value_t = false, conflicts_with_all = ["half_block", "sixel"])]
+    braille: bool,
+
+    /// Luma from 0 to 255 above which a pixel's Braille dot is raised
+    #[arg(long, default_value_t = 128)]
+    braille_threshold: u8,
+
+    /// Overlay the frame rate actually achieved on streams, averaged over the last few frames
+    #[arg(long, default_value_t = false)]
+    show_fps: bool,
+
+    /// Fraction of the terminal, above 0 and at most 1, that --resize fits the image into
+    #[arg(long, default_value_t = 1.0, value_parser = parse_scale)]
+    scale: f32,
+
+    /// Flip frames horizontally. On by default for the webcam feed, selfie style; pass
+    /// --mirror=false to turn that off
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    mirror: Option<bool>,
+
+    /// Rotate clockwise by this many degrees before rendering
+    #[arg(long, value_enum)]
+    rotate: Option<Rotate>,
+
+    /// Draw in pure black and white, with full cells for pixels whose luma from 0 to 255 is
+    /// above this and blank ones for the rest
+    #[arg(long, conflicts_with_all = ["dither", "charset"])]
+    threshold: Option<u8>,
+
+    /// How many characters wide every cell is. Narrower cells look taller on most fonts
+    #[arg(long, default_value_t = 3, value_parser = parse_positive::<usize>)]
+    cell_width: usize,
+
+    /// Print nothing but the ASCII art: no initial screen clear and no warnings. Errors are
+    /// still reported
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Gamma correction. Around 2.2 brings out shadow detail in photos
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive::<f32>)]
+    gamma: f32,
+
+    /// Play the audio track of videos with ffplay. Frames play at the video's own frame rate to
+    /// stay in sync with it, so --fps and pausing (which doesn't pause the audio) put them out
+    /// of sync
+    #[arg(long, default_value_t = false)]
+    audio: bool,
+
+    /// Snap every color channel to this many evenly spaced levels, for a posterized look.
+    /// Only colors change, not the glyphs
+    #[arg(long, value_parser = parse_levels)]
+    posterize: Option<u8>,
+
+    /// Resolution to ask the camera for, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_resolution)]
+    camera_resolution: Option<(u32, u32)>,
+
+    /// Frame rate to ask the camera for
+    #[arg(long, value_parser = parse_positive::<u32>)]
+    camera_fps: Option<u32>,
+
+    /// Play GIFs and APNGs this many times, whatever --loop-animation or the file say
+    #[arg(long, value_parser = parse_positive::<usize>)]
+    loop_count: Option<usize>,
+
+    /// Print the number of every stream frame and the time since the stream started on a line
+    /// below it
+    #[arg(long, default_value_t = false)]
+    frame_numbers: bool,
+
+    /// Render the image on the clipboard instead of a file
+    #[arg(long, default_value_t = false, conflicts_with = "webcam_feed")]
+    clipboard: bool,
+
+    /// Only render this region of the image, as x,y,width,height in pixels
+    #[arg(long, value_parser = parse_crop)]
+    crop: Option<(u32, u32, u32, u32)>,
+
+    /// Stretch faded images over the full brightness range before rendering
+    #[arg(long, default_value_t = false)]
+    auto_contrast: bool,
+
+    /// Re-render the image every time the file changes, until q is pressed
+    #[arg(long, default_value_t = false)]
+    watch: bool,
+
+    /// How --resize fits the image to the terminal
+    #[arg(long, value_enum, default_value_t = FitMode::Auto)]
+    fit: FitMode,
+
+    /// Snap colors to a palette: gameboy, cga, sepia, or a comma separated list of #rrggbb
+    /// colors
+    #[arg(long, value_parser = parse_palette)]
+    palette: Option<Palette>,
+
+    /// Formula pixel brightness is worked out with when picking glyphs
+    #[arg(long, value_enum, default_value_t = LumaMode::Average)]
+    luma: LumaMode,
+
+    /// Drop frames when rendering falls behind, to keep streams in step with the clock
+    #[arg(long, default_value_t = false)]
+    realtime: bool,
+
+    /// Written after every frame when streaming into a named pipe given as --output. Defaults
+    /// to a form feed
+    #[arg(long, default_value = "\u{c}", hide_default_value = true)]
+    frame_separator: String,
+
+    /// Fit tall images to the terminal's width and print them whole, so they scroll into the
+    /// scrollback. Short for --resize --fit width --no-clear
+    #[arg(long, default_value_t = false, conflicts_with = "fit")]
+    fit_scroll: bool,
+
+    /// Print a summary of frame timings to stderr when a stream ends
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Blank columns to leave after every cell, for a sparser look
+    #[arg(long, default_value_t = 0)]
+    cell_spacing: usize,
+
+    /// Show a bar in the average color of every frame of a stream, on the bottom line
+    #[arg(long, default_value_t = false)]
+    color_bar: bool,
+
+    /// Show the photographic negative of the image's colors. Unlike --invert, this changes the
+    /// colors themselves as well as the glyphs picked for them
+    #[arg(long, default_value_t = false)]
+    negate: bool,
+
+    /// Show only the first frame of an animation or video, as a still
+    #[arg(long, default_value_t = false)]
+    first_frame: bool,
+
+    /// Refuse images with more pixels than this, so corrupt or hostile files can't use up all
+    /// the memory
+    #[arg(long, default_value_t = 100_000_000)]
+    max_pixels: u64,
+
+    /// How many more times to try fetching a URL after a timeout or server error. The wait
+    /// between tries doubles every time
+    #[arg(long, default_value_t = 2)]
+    retries: u32,
+
+    /// Seconds to give a URL fetch before giving up on it
+    #[arg(long, default_value_t = 30, value_parser = parse_positive::<u64>)]
+    timeout: u64,
+
+    /// Also record the rendered frames of a stream into a video (or GIF) at this path, encoded
+    /// by ffmpeg. Frames are drawn like the SVG output, so Braille and half blocks aren't kept
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Leave the cursor visible while rendering, rather than hiding it until the program exits
+    #[arg(long, default_value_t = false)]
+    keep_cursor: bool,
+
+    /// Ring the terminal bell once rendering is done, or once a stream ends
+    #[arg(long, default_value_t = false)]
+    bell: bool,
+
+    /// Milliseconds to pause on the last frame before a looping animation starts over. --realtime
+    /// would take the pause for falling behind, so they can't be combined
+    #[arg(long, conflicts_with = "realtime")]
+    loop_delay: Option<u64>,
+
+    /// Cut off art that's wider than the terminal, rather than letting its lines wrap
+    #[arg(long, default_value_t = false)]
+    truncate: bool,
+
+    /// File listing paths or URLs to show one after another, one per line. Blank lines and lines
+    /// starting with # are skipped
+    #[arg(long)]
+    input_list: Option<PathBuf>,
+
+    /// Sharpen the resized image with an unsharp mask of this radius, so edges stand out in the
+    /// art. Up to 10, and 0 leaves it alone
+    #[arg(long, default_value_t = 0.0)]
+    sharpen: f32,
+
+    /// Print the format, size and dimensions of inputs to stderr before showing them, along with
+    /// the frame count and duration of videos and animated GIFs, PNGs and WebPs
+    #[arg(long, default_value_t = false)]
+    info: bool,
+
+    /// Blur the image with a Gaussian of this sigma before resizing, for a softer look. 0 leaves
+    /// it alone
+    #[arg(long, default_value_t = 0.0)]
+    blur: f32,
+
+    /// Show only this frame of an animation or video, counting from 0, as a still
+    #[arg(long, conflicts_with_all = ["first_frame", "loop_animation", "loop_count"])]
+    frame_index: Option<usize>,
+
+    /// Character encoding of the output. Anything but UTF-8 falls back to the --ascii-only ramp
+    #[arg(long, value_enum, default_value_t = Encoding::Auto)]
+    encoding: Encoding,
+
+    /// Milliseconds to keep the final image or frame on screen before exiting, or 0 to keep it
+    /// until a key is pressed
+    #[arg(long)]
+    hold: Option<u64>,
+
+    /// Shrink the source by this factor before anything else is done to it, trading detail for
+    /// speed on huge images
+    #[arg(long, default_value_t = 1, value_parser = parse_positive::<u32>)]
+    subsample: u32,
+
+    /// Read stdin as raw pixels instead of an encoded image, given as WIDTHxHEIGHTxCHANNELS with
+    /// 1 (gray), 3 (RGB) or 4 (RGBA) channels of a byte each, like 640x480x3
+    #[arg(long, value_parser = parse_raw)]
+    raw: Option<(u32, u32, u8)>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FilterArg {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FitMode {
+    /// Fit the whole image in the terminal
+    Auto,
+    /// Fill the terminal's width, even if the image runs past the bottom
+    Width,
+    /// Fill the terminal's height, even if the image runs past the side
+    Height,
+    /// Fill the whole terminal, ignoring the aspect ratio
+    Stretch,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    /// UTF-8 if the terminal's locale or code page is, and ASCII otherwise
+    Auto,
+    /// Always ASCII
+    Ascii,
+    /// Always UTF-8
+    Utf8,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Rotate {
+    #[value(name = "90")]
+    R90,
+    #[value(name = "180")]
+    R180,
+    #[value(name = "270")]
+    R270,
+}
+
+impl From<FilterArg> for FilterType {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            FilterArg::Nearest => FilterType::Nearest,
+            FilterArg::Triangle => FilterType::Triangle,
+            FilterArg::CatmullRom => FilterType::CatmullRom,
+            FilterArg::Gaussian => FilterType::Gaussian,
+            FilterArg::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl Args {
+    fn use_color(&self) -> bool {
+        self.colored && !self.grayscale
+    }
+
+    fn mirrored(&self) -> bool {
+        self.mirror.unwrap_or(self.webcam_feed)
+    }
+
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            colored: self.use_color(),
+            block_character: self.block_character,
+            cell_width: self.cell_width,
+            charset: self.charset.clone(),
+            invert: self.invert,
+            // Grayscale output has always been bucketed by Rec. 601 luma
+            luma: if self.grayscale && self.luma == LumaMode::Average {
+                LumaMode::Rec601
+            } else {
+                self.luma
+            },
+            color_mode: self.color_mode,
+            brightness: self.brightness,
+            contrast: self.contrast,
+            auto_contrast: self.auto_contrast,
+            gamma: self.gamma,
+            posterize: self.posterize,
+            palette: self.palette.clone(),
+            edges: self.edges,
+            dither: self.dither,
+            half_block: self.half_block,
+            background: self.background,
+            ascii_only: self.ascii_only,
+            threshold: self.threshold,
+            braille: self.braille,
+            braille_threshold: self.braille_threshold,
+            cell_spacing: self.cell_spacing,
+            negate: self.negate,
+        }
+    }
+}
+
+fn parse_hex_color(color: &str) -> Result<(u8, u8, u8), String> {
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or_else(|| String::from(INVALID_HEX_COLOR_ERR))?;
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| String::from(INVALID_HEX_COLOR_ERR))
+    };
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Parse a time as HH:MM:SS, MM:SS or plain seconds, each optionally with a fractional part
+fn parse_timestamp(timestamp: &str) -> Result<Duration, String> {
+    let parts: Vec<&str> = timestamp.split(':').collect();
+    if parts.len() > 3 {
+        return Err(String::from(INVALID_TIMESTAMP_ERR));
+    }
+    let seconds = parts.iter().enumerate().try_fold(0.0, |total, (i, part)| {
+        let value = part
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            // Only the last part may have a fraction, and only the first may be 60 or more
+            .filter(|_| i + 1 == parts.len() || !part.contains('.'))
+            .filter(|value| i == 0 || *value < 60.0)
+            .ok_or_else(|| String::from(INVALID_TIMESTAMP_ERR))?;
+        Ok::<f64, String>(total * 60.0 + value)
+    })?;
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Colors of a --palette. Clap would take `Option<Vec<_>>` as a list of values rather than one
+/// value parsed into a list, hence the alias.
+type Palette = Vec<(u8, u8, u8)>;
+
+fn parse_palette(palette: &str) -> Result<Palette, String> {
+    match palette {
+        "gameboy" => Ok(GAMEBOY_PALETTE.to_vec()),
+        "cga" => Ok(CGA_PALETTE.to_vec()),
+        "sepia" => Ok(SEPIA_PALETTE.to_vec()),
+        _ => palette
+            .split(',')
+            .map(|c| parse_hex_color(c.trim()))
+            .collect(),
+    }
+}
+
+fn parse_charset(charset: &str) -> Result<String, String> {
+    if charset.is_empty() {
+        Err(String::from(EMPTY_CHARSET_ERR))
+    } else {
+        Ok(charset.to_string())
+    }
 }
 
-const HEAT_MAP_LENGTH: usize = 16;
+fn parse_positive<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr + PartialOrd + Default,
+    T::Err: std::fmt::Display,
+{
+    match value.parse::<T>() {
+        Ok(value) if value > T::default() => Ok(value),
+        Ok(_) => Err(String::from(NOT_POSITIVE_ERR)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Frame rates are at least MIN_FPS, since the delay between frames has to fit in a Duration
+fn parse_fps(fps: &str) -> Result<f64, String> {
+    match fps.parse::<f64>() {
+        Ok(fps) if fps >= MIN_FPS => Ok(fps),
+        Ok(_) => Err(String::from(FPS_TOO_LOW_ERR)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_scale(scale: &str) -> Result<f32, String> {
+    match scale.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale <= 1.0 => Ok(scale),
+        Ok(_) => Err(String::from(SCALE_OUT_OF_RANGE_ERR)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_levels(levels: &str) -> Result<u8, String> {
+    match levels.parse::<u8>() {
+        Ok(levels) if levels >= 2 => Ok(levels),
+        Ok(_) => Err(String::from(TOO_FEW_LEVELS_ERR)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_resolution(resolution: &str) -> Result<(u32, u32), String> {
+    let (width, height) = resolution
+        .split_once('x')
+        .ok_or_else(|| String::from(INVALID_RESOLUTION_ERR))?;
+    let dimension = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|value| *value > 0)
+            .ok_or_else(|| String::from(INVALID_RESOLUTION_ERR))
+    };
+    Ok((dimension(width)?, dimension(height)?))
+}
+
+fn parse_raw(raw: &str) -> Result<(u32, u32, u8), String> {
+    let (resolution, channels) = raw
+        .rsplit_once('x')
+        .ok_or_else(|| String::from(INVALID_RAW_ERR))?;
+    let (width, height) =
+        parse_resolution(resolution).map_err(|_| String::from(INVALID_RAW_ERR))?;
+    match channels.parse::<u8>() {
+        Ok(channels @ (1 | 3 | 4)) => Ok((width, height, channels)),
+        _ => Err(String::from(INVALID_RAW_ERR)),
+    }
+}
+
+fn parse_crop(crop: &str) -> Result<(u32, u32, u32, u32), String> {
+    let values = crop
+        .split(',')
+        .map(|value| value.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| String::from(INVALID_CROP_ERR))?;
+    match values[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok((x, y, width, height)),
+        _ => Err(String::from(INVALID_CROP_ERR)),
+    }
+}
+
+/// The four greens of the original Game Boy screen
+const GAMEBOY_PALETTE: [(u8, u8, u8); 4] =
+    [(15, 56, 15), (48, 98, 48), (139, 172, 15), (155, 188, 15)];
+/// CGA's high intensity palette 1, with its black background
+const CGA_PALETTE: [(u8, u8, u8); 4] = [(0, 0, 0), (85, 255, 255), (255, 85, 255), (255, 255, 255)];
+/// Browns from shadow to highlight of an old photo
+const SEPIA_PALETTE: [(u8, u8, u8); 5] = [
+    (43, 29, 14),
+    (94, 66, 38),
+    (150, 113, 71),
+    (201, 165, 113),
+    (240, 222, 186),
+];
+/// Font size of SVG output, which is also the height of a line
+const SVG_FONT_SIZE: f32 = 10.0;
+/// Width of a character as a fraction of the font size, about right for most monospace fonts
+const SVG_CHAR_WIDTH: f32 = 0.6;
+/// The font recordings are drawn with, and its size in pixels
+const RECORD_FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+const RECORD_FONT_SIZE: f32 = 14.0;
+/// Largest --sharpen radius, past which the mask only adds halos
+const MAX_SHARPEN: f32 = 10.0;
+const ANSI_RESET: &str = "\x1b[0m";
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(200);
+const MIN_FPS: f64 = 0.001;
+/// Number of frames the --show-fps average is taken over
+const FPS_WINDOW: usize = 10;
+/// How long slideshows show every image for without --animation-delay
+const DEFAULT_SLIDE_DELAY: Duration = Duration::from_secs(3);
+/// How long --watch waits for writes to stop before re-rendering
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+/// Size of the reads downloads are made in, and so how often their progress updates
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// How long to wait before the first retry of a failed fetch
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// How long the terminal size has to hold still before a resize is redrawn
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+const HOLD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Browsers play zero-delay GIF and APNG frames at this delay, so do the same
+const ZERO_FRAME_DELAY_FALLBACK: Duration = Duration::from_millis(100);
+const FALLBACK_TERMINAL_SIZE: (u16, u16) = (80, 24);
 const INVALID_URI_ERR: &str =
     "No valid input media provided (Webcam/File on your local file system/Network URL)";
+const FFMPEG_CLIP_ERR: &str = "Couldn't start ffmpeg to cut the requested part of the video";
 const UNEXPECTED_FILE_TYPE_ERR: &str =
-    "Provided file type was not expected (Not MP4/MKV/JPG/PNG/GIF)";
+    "Provided file type was not expected (Not MP4/MKV/JPG/PNG/GIF/WEBP/BMP/AVIF/TIFF)";
+const NO_AVIF_ERR: &str = "AVIF images need image2ascii built with --features avif";
 const TERMINAL_TOO_SMALL_ERR: &str = "I don't like zero sized terminals";
-const HEAT_MAP: [&str; HEAT_MAP_LENGTH] = [
-    "   ",
-    "...",
-    "´´´",
-    ":::",
-    "~~~",
-    "+++",
-    "iii",
-    "xxx",
-    "!!!",
-    "III",
-    "###",
-    "$$$",
-    "XXX",
-    "▄▄▄",
-    "■■■",
-    "███",
-];
+const NO_TRUECOLOR_WARNING: &str =
+    "Warning: this terminal doesn't seem to support 24-bit color, try --color-mode ansi256";
+const NO_KITTY_WARNING: &str =
+    "Warning: this terminal doesn't seem to support the kitty graphics protocol";
+const WIDE_CHARSET_WARNING: &str =
+    "Warning: the charset has double width characters, which some terminals draw narrower";
+const LINES_WRAPPED_WARNING: &str =
+    "Warning: the art was wider than the terminal and wrapped, try --truncate or --fit width";
+const NO_ITERM_WARNING: &str = "Warning: this terminal doesn't seem to be iTerm2";
+const NO_FFPLAY_WARNING: &str =
+    "Warning: couldn't start ffplay, so the video will play without audio";
+const NOT_POSITIVE_ERR: &str = "Value must be greater than zero";
+const FPS_TOO_LOW_ERR: &str = "Frame rate must be at least 0.001";
+const SCALE_OUT_OF_RANGE_ERR: &str = "Scale must be greater than 0 and at most 1";
+const TOO_FEW_LEVELS_ERR: &str = "There must be at least 2 levels";
+const INVALID_RESOLUTION_ERR: &str = "Resolutions must be written as WIDTHxHEIGHT, like 1280x720";
+const INVALID_CROP_ERR: &str = "Crops must be written as x,y,width,height with a nonzero size";
+const CROP_OUT_OF_BOUNDS_ERR: &str = "The crop region doesn't fit in the image";
+const INVALID_HEX_COLOR_ERR: &str = "Colors must be written as #rrggbb";
+const INVALID_TIMESTAMP_ERR: &str = "Times must be written as HH:MM:SS, MM:SS or seconds";
+const NO_CLIPBOARD_IMAGE_ERR: &str = "The clipboard doesn't hold an image";
+const EMPTY_DIRECTORY_ERR: &str = "The directory doesn't have any images in it";
+const EMPTY_CHARSET_ERR: &str = "The charset needs at least one character";
+const MULTIPLE_OUTPUT_INPUTS_ERR: &str = "--output only takes a single input";
+const RECORDER_CLOSED_ERR: &str = "ffmpeg stopped taking frames for the recording";
+const EMPTY_INPUT_LIST_ERR: &str = "The input list doesn't have any paths or URLs in it";
+const FRAME_INDEX_OUT_OF_RANGE_ERR: &str =
+    "The source has fewer frames than --frame-index asks for";
+const INVALID_RAW_ERR: &str =
+    "Raw pixels must be described as WIDTHxHEIGHTxCHANNELS with 1, 3 or 4 channels, like 640x480x3";
+const RAW_SIZE_MISMATCH_ERR: &str = "The raw pixels on stdin don't add up to the size --raw gives";
+const TOO_MANY_PIXELS_ERR: &str = "The image has more pixels than --max-pixels allows";
 
-fn resize_img(img: DynamicImage) -> Result<DynamicImage> {
-    let canvas_dimensions = terminal::size()?;
-    let canvas_dimensions = (
-        canvas_dimensions.0 as u32 / 3,
-        canvas_dimensions.1 as u32 - 3,
-    );
-    let img_dimensions = img.dimensions();
-    let wr = img_dimensions
-        .1
-        .checked_div(canvas_dimensions.0)
-        .context(TERMINAL_TOO_SMALL_ERR)?;
-    let hr = img_dimensions
-        .1
-        .checked_div(canvas_dimensions.1)
-        .context(TERMINAL_TOO_SMALL_ERR)?;
-    Ok(if hr > wr {
-        img.resize(
-            canvas_dimensions.1 * img_dimensions.0 / img_dimensions.1,
-            canvas_dimensions.1,
-            FilterType::Nearest,
-        )
+/// Set once art is printed wider than the terminal, so the warning can wait until the end
+static LINES_WRAPPED: AtomicBool = AtomicBool::new(false);
+/// How many image rows are packed into one row of terminal cells
+fn pixels_per_row(args: &Args) -> u32 {
+    if args.braille {
+        4
+    } else if args.half_block {
+        2
     } else {
-        img.resize(
-            canvas_dimensions.0,
-            canvas_dimensions.0 * img_dimensions.1 / img_dimensions.0,
-            FilterType::Nearest,
-        )
-    })
+        1
+    }
 }
 
-fn print_img(img: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>, args: &Args) -> Result<()> {
-    //TODO fix banding in some resolutions of the terminal
-    let mut stdout = stdout();
-    let img = DynamicImage::ImageRgb8(img);
-    let img = if args.resize { resize_img(img)? } else { img };
-    let (width, height) = img.dimensions();
-    stdout.execute(cursor::MoveTo(0, 0)).unwrap();
-    let pixels_with_value: Vec<(u8, u8, u8, u8)> = img
-        .pixels()
-        .map(|p| {
-            let p = p.2.channels();
-            (
-                p[0],
-                p[1],
-                p[2],
-                (((p[0] as u32 + p[1] as u32 + p[2] as u32) / 3) / (256 / HEAT_MAP_LENGTH) as u32)
-                    as u8,
-            )
-        })
-        .map(|(r, g, b, p)| (r, g, b, p))
-        .collect();
-    for i in 0..height {
-        for j in i * width..i * width + width {
-            let p = pixels_with_value[j as usize];
-            let text = if args.block_character {
-                "███"
-            } else {
-                HEAT_MAP[p.3 as usize]
-            };
-            if args.colored {
-                queue!(
-                    stdout,
-                    PrintStyledContent(text.with(Color::Rgb {
-                        r: p.0,
-                        g: p.1,
-                        b: p.2
-                    }))
-                )?
-            } else {
-                queue!(stdout, Print(text))?
+/// How many image columns are packed into one glyph
+fn pixels_per_column(args: &Args) -> u32 {
+    if args.braille {
+        2
+    } else {
+        1
+    }
+}
+
+/// How many terminal columns one glyph takes up, including the spacing after it
+fn glyph_columns(args: &Args) -> u32 {
+    let glyph = if args.braille {
+        1
+    } else if args.half_block {
+        args.cell_width
+    } else {
+        args.cell_width * glyph_width(&args.render_options())
+    };
+    (glyph + args.cell_spacing) as u32
+}
+
+/// Terminal columns taken up by an image `width` pixels wide
+fn output_columns(width: u32, args: &Args) -> u32 {
+    width.div_ceil(pixels_per_column(args)) * glyph_columns(args)
+}
+
+/// Width:height ratio of a rendered pixel, which is --cell-width plus --cell-spacing terminal
+/// cells wide and one high (or half a cell high in half block mode, and half a cell wide and a
+/// quarter high in Braille mode)
+fn pixel_aspect(args: &Args) -> f32 {
+    if args.sixel {
+        // Drawn as actual pixels, which are square
+        return 1.0;
+    }
+    glyph_columns(args) as f32 / pixels_per_column(args) as f32
+        * args.cell_aspect
+        * pixels_per_row(args) as f32
+}
+
+/// (width, height) to draw the image at on the canvas. Apart from in stretch mode, the image
+/// keeps its visual aspect ratio once every pixel is drawn `pixel_aspect` times wider than it
+/// is high. Auto mode picks the largest size that fits, and the others fill one dimension and
+/// let the other overflow.
+fn fit_dimensions(
+    img: (u32, u32),
+    canvas: (u32, u32),
+    pixel_aspect: f32,
+    fit: FitMode,
+) -> (u32, u32) {
+    let rows_per_column = img.1 as f32 / img.0.max(1) as f32 * pixel_aspect;
+    let fit_width = (
+        canvas.0,
+        ((canvas.0 as f32 * rows_per_column).round() as u32).max(1),
+    );
+    let fit_height = (
+        ((canvas.1 as f32 / rows_per_column).round() as u32).max(1),
+        canvas.1,
+    );
+    match fit {
+        FitMode::Auto if fit_width.1 <= canvas.1 => fit_width,
+        FitMode::Auto => fit_height,
+        FitMode::Width => fit_width,
+        FitMode::Height => fit_height,
+        FitMode::Stretch => canvas,
+    }
+}
+
+/// Size of the terminal in (columns, lines). Piped output can report no terminal or a zero
+/// sized one, which falls back to a conventional 80x24; --width and --height skip this entirely.
+fn terminal_size() -> (u16, u16) {
+    usable_terminal_size(terminal::size())
+}
+
+fn usable_terminal_size(size: std::io::Result<(u16, u16)>) -> (u16, u16) {
+    match size {
+        Ok((columns, lines)) if columns > 0 && lines > 0 => (columns, lines),
+        _ => FALLBACK_TERMINAL_SIZE,
+    }
+}
+
+/// Resize target of streams, as (source dimensions, target dimensions). It's kept until the
+/// terminal is resized, so frames don't each redo the fitting.
+type SizeCache = Mutex<Option<((u32, u32), (u32, u32))>>;
+
+fn resize_img(
+    img: DynamicImage,
+    args: &Args,
+    size_cache: Option<&SizeCache>,
+) -> Result<DynamicImage> {
+    let dimensions = img.dimensions();
+    let cached = size_cache
+        .and_then(|cache| *cache.lock().unwrap())
+        .filter(|(source, _)| *source == dimensions);
+    let (width, height) = match cached {
+        Some((_, target)) => target,
+        None => {
+            let target = resize_target(dimensions, args)?;
+            if let Some(cache) = size_cache {
+                *cache.lock().unwrap() = Some((dimensions, target));
             }
+            target
+        }
+    };
+    Ok(img.resize_exact(width, height, args.filter.into()))
+}
+
+/// Dimensions that fit an image of the given dimensions into the terminal
+fn resize_target(dimensions: (u32, u32), args: &Args) -> Result<(u32, u32)> {
+    let canvas_dimensions = terminal_size();
+    let canvas_dimensions = (
+        canvas_dimensions.0 as u32 / glyph_columns(args) * pixels_per_column(args),
+        (canvas_dimensions.1 as u32).saturating_sub(3) * pixels_per_row(args),
+    );
+    let canvas_dimensions = (
+        (canvas_dimensions.0 as f32 * args.scale) as u32,
+        (canvas_dimensions.1 as f32 * args.scale) as u32,
+    );
+    if canvas_dimensions.0 == 0 || canvas_dimensions.1 == 0 {
+        return Err(anyhow::anyhow!(TERMINAL_TOO_SMALL_ERR));
+    }
+    Ok(fit_dimensions(
+        dimensions,
+        canvas_dimensions,
+        pixel_aspect(args),
+        args.fit,
+    ))
+}
+
+/// Resize the image to explicit dimensions, deriving a missing one from the aspect ratio
+fn resize_img_to(
+    img: DynamicImage,
+    width: Option<u32>,
+    height: Option<u32>,
+    args: &Args,
+) -> DynamicImage {
+    let (img_width, img_height) = img.dimensions();
+    let rows_per_column = img_height as f32 / img_width.max(1) as f32 * pixel_aspect(args);
+    let width = width.map(|width| width * pixels_per_column(args));
+    let height = height.map(|height| height * pixels_per_row(args));
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) => (width, height),
+        // Very wide or tall images would otherwise round down to nothing
+        (Some(width), None) => (
+            width,
+            ((width as f32 * rows_per_column).round() as u32).max(1),
+        ),
+        (None, Some(height)) => (
+            ((height as f32 / rows_per_column).round() as u32).max(1),
+            height,
+        ),
+        (None, None) => (img_width, img_height),
+    };
+    img.resize_exact(width, height, args.filter.into())
+}
+
+/// Write the ASCII rows to a file. No escape sequences are written, even in colored mode.
+fn write_img<P>(
+    path: P,
+    pixels_with_value: &[(u8, u8, u8, usize)],
+    width: u32,
+    heat_map: &[String],
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    for row in pixels_with_value.chunks(width.max(1) as usize) {
+        for p in row {
+            file.write_all(glyph(p, heat_map).as_bytes())?;
         }
-        queue!(stdout, Print("\n"))?;
+        file.write_all(b"\n")?;
     }
-    stdout.flush()?;
+    file.flush()?;
     Ok(())
 }
 
-fn print_stream<I>(stream: I, args: &Args) -> Result<()>
+/// Write the grid of cells as JSON, with every cell's glyph, color and heat map index, so other
+/// tools can render it however they like
+fn write_json<P>(
+    path: P,
+    pixels_with_value: &[(u8, u8, u8, usize)],
+    width: u32,
+    heat_map: &[String],
+) -> Result<()>
 where
-    I: IntoIterator<Item = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>>,
+    P: AsRef<Path>,
 {
-    let mut canvas_size = terminal::size()?;
-    let mut events = EventManager::default().append(
-        || {
-            let size = terminal::size().unwrap();
-            if canvas_size != size {
-                canvas_size = size;
-                true
-            } else {
-                false
-            }
-        },
-        || {
-            execute!(stdout(), Clear(ClearType::All)).unwrap();
-        },
-    );
-
-    stream.into_iter().for_each(|frame| {
-        print_img(frame, &args).unwrap();
-        events.run();
-        sleep(Duration::from_millis(args.animation_delay));
+    let rows: Vec<serde_json::Value> = pixels_with_value
+        .chunks(width.max(1) as usize)
+        .map(|row| {
+            row.iter()
+                .map(|p| {
+                    serde_json::json!({
+                        "char": glyph(p, heat_map),
+                        "r": p.0,
+                        "g": p.1,
+                        "b": p.2,
+                        "index": p.3,
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let grid = serde_json::json!({
+        "width": width,
+        "height": rows.len(),
+        "cells": rows,
     });
+    let mut file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut file, &grid)?;
+    file.flush()?;
     Ok(())
 }
 
-fn print_gif<P>(path: P, args: &Args) -> Result<()>
+/// Write the ASCII art as an SVG with a text element per cell, so it scales to any size
+fn write_svg<P>(
+    path: P,
+    pixels_with_value: &[(u8, u8, u8, usize)],
+    width: u32,
+    heat_map: &[String],
+    opts: &RenderOptions,
+) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    let file = std::fs::File::open(path)?;
-    let frames = GifDecoder::new(file)?
-        .into_frames()
-        .collect_frames()?
-        .into_iter()
-        .map(Frame::into_buffer)
-        .map(|f| f.convert());
-    if args.loop_animation {
-        print_stream(frames.cycle(), args)
-    } else {
-        print_stream(frames, args)
+    let rows: Vec<_> = pixels_with_value.chunks(width.max(1) as usize).collect();
+    let cell_width = SVG_FONT_SIZE
+        * SVG_CHAR_WIDTH
+        * (opts.cell_width * glyph_width(opts) + opts.cell_spacing) as f32;
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">",
+        cell_width * width as f32,
+        SVG_FONT_SIZE * rows.len() as f32
+    )?;
+    let (r, g, b) = opts.background.unwrap_or((0, 0, 0));
+    writeln!(
+        file,
+        "<rect width=\"100%\" height=\"100%\" fill=\"#{:02x}{:02x}{:02x}\"/>",
+        r, g, b
+    )?;
+    writeln!(
+        file,
+        "<g font-family=\"monospace\" font-size=\"{}\" xml:space=\"preserve\">",
+        SVG_FONT_SIZE
+    )?;
+    for (y, row) in rows.iter().enumerate() {
+        for (x, p) in row.iter().enumerate() {
+            let text = glyph(p, heat_map);
+            // Blank cells would only show the background anyway
+            if text.trim().is_empty() {
+                continue;
+            }
+            let (r, g, b) = if opts.colored {
+                (p.0, p.1, p.2)
+            } else {
+                (255, 255, 255)
+            };
+            writeln!(
+                file,
+                "<text x=\"{}\" y=\"{}\" fill=\"#{:02x}{:02x}{:02x}\">{}</text>",
+                cell_width * x as f32,
+                // Text sits on its baseline, so push it down most of a line
+                SVG_FONT_SIZE * (y as f32 + 0.8),
+                r,
+                g,
+                b,
+                html_escape(text)
+            )?;
+        }
     }
+    writeln!(file, "</g>")?;
+    writeln!(file, "</svg>")?;
+    file.flush()?;
+    Ok(())
 }
 
-fn print_camera(args: &Args) -> Result<()> {
-    let mut camera = CameraIter::default();
-    if args.loop_animation {
-        print_stream(camera, args)
-    } else {
-        print_img(
-            camera.next().expect("Failed to get frame from camera"),
-            args,
-        )
+/// Write the rows as they'd be printed to the terminal, escape sequences and all, so that
+/// `cat` shows them in color later
+fn write_ansi<P>(path: P, rows: &[String]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    for row in rows {
+        // Reset at the end of every line, so colors never bleed into what comes after
+        writeln!(file, "{}{}", row, ANSI_RESET)?;
     }
+    file.flush()?;
+    Ok(())
 }
 
-fn handle_fs_path<P>(args: &Args, path: P) -> Result<()>
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Write the ASCII rows as a minimal HTML document, with a colored span per cell in colored mode
+fn write_html<P>(
+    path: P,
+    pixels_with_value: &[(u8, u8, u8, usize)],
+    width: u32,
+    heat_map: &[String],
+    opts: &RenderOptions,
+) -> Result<()>
 where
-    P: Into<PathBuf>,
+    P: AsRef<Path>,
 {
-    let path: PathBuf = path.into();
-    match FileFormat::from_file(&path)? {
-        FileFormat::Mpeg4Part14Video | FileFormat::MatroskaVideo => print_stream(
-            ffmpeg_cmdline_utils::FfmpegFrameReaderBuilder::new(std::path::PathBuf::from(path))
-                .spawn()?
-                .0,
-            args,
-        ),
-        FileFormat::PortableNetworkGraphics | FileFormat::JointPhotographicExpertsGroup => {
-            print_img(ImageReader::open(&path)?.decode()?.to_rgb8(), args)
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "<!DOCTYPE html>")?;
+    writeln!(file, "<html>")?;
+    writeln!(
+        file,
+        "<head><meta charset=\"utf-8\"><title>image2ascii</title></head>"
+    )?;
+    let (r, g, b) = opts.background.unwrap_or((0, 0, 0));
+    writeln!(
+        file,
+        "<body style=\"background-color:#{:02x}{:02x}{:02x}\">",
+        r, g, b
+    )?;
+    writeln!(
+        file,
+        "<pre style=\"color:#ffffff;font-family:monospace;line-height:1\">"
+    )?;
+    for row in pixels_with_value.chunks(width.max(1) as usize) {
+        for p in row {
+            let text = html_escape(glyph(p, heat_map));
+            if opts.colored {
+                write!(
+                    file,
+                    "<span style=\"color:#{:02x}{:02x}{:02x}\">{}</span>",
+                    p.0, p.1, p.2, text
+                )?;
+            } else {
+                write!(file, "{}", text)?;
+            }
         }
-        FileFormat::GraphicsInterchangeFormat => print_gif(path, args),
-        _ => Err(anyhow::anyhow!(UNEXPECTED_FILE_TYPE_ERR)),
+        writeln!(file)?;
     }
+    writeln!(file, "</pre>")?;
+    writeln!(file, "</body>")?;
+    writeln!(file, "</html>")?;
+    file.flush()?;
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let path = args.file_path.clone();
-    execute!(stdout(), Clear(ClearType::All)).unwrap();
-    if args.webcam_feed {
-        print_camera(&args)
-    } else if std::path::Path::new(&path).is_file() {
-        handle_fs_path(&args, &path)
-    } else if let Ok(url) = reqwest::Url::parse(&path) {
-        let bytes = &reqwest::blocking::get(url)?.bytes()?[..];
-        let mut file = tempfile::Builder::new()
-            .suffix((String::from(".") + FileFormat::from_bytes(bytes).extension()).as_str())
-            .tempfile()?;
-        file.as_file_mut().write(bytes)?;
-        handle_fs_path(&args, file.path())
+/// Rows end with a carriage return as well while a stream has the terminal in raw mode, which
+/// would otherwise staircase them
+fn line_ending() -> &'static str {
+    if terminal::is_raw_mode_enabled().unwrap_or(false) {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Crop, flip, rotate, subsample, blur, resize and sharpen an image as the arguments ask
+fn prepare_img(
+    img: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    args: &Args,
+    size_cache: Option<&SizeCache>,
+) -> Result<DynamicImage> {
+    //TODO fix banding in some resolutions of the terminal
+    let img = match args.crop {
+        Some((x, y, width, height)) => {
+            let fits_width = x
+                .checked_add(width)
+                .is_some_and(|right| right <= img.width());
+            let fits_height = y
+                .checked_add(height)
+                .is_some_and(|bottom| bottom <= img.height());
+            if !fits_width || !fits_height {
+                return Err(anyhow::anyhow!(
+                    "{} ({}x{})",
+                    CROP_OUT_OF_BOUNDS_ERR,
+                    img.width(),
+                    img.height()
+                ));
+            }
+            image::imageops::crop_imm(&img, x, y, width, height).to_image()
+        }
+        None => img,
+    };
+    // Flip and rotate before resizing, while the image is still at full resolution
+    let img = if args.mirrored() {
+        image::imageops::flip_horizontal(&img)
+    } else {
+        img
+    };
+    let img = match args.rotate {
+        Some(Rotate::R90) => image::imageops::rotate90(&img),
+        Some(Rotate::R180) => image::imageops::rotate180(&img),
+        Some(Rotate::R270) => image::imageops::rotate270(&img),
+        None => img,
+    };
+    let img = if args.subsample > 1 {
+        image::imageops::thumbnail(
+            &img,
+            (img.width() / args.subsample).max(1),
+            (img.height() / args.subsample).max(1),
+        )
+    } else {
+        img
+    };
+    let img = if args.blur > 0.0 {
+        image::imageops::blur(&img, args.blur)
+    } else {
+        img
+    };
+    let img = DynamicImage::ImageRgb8(img);
+    let img = match (args.width, args.height) {
+        // There's no cell grid to fit Sixel output to
+        (None, None) if args.resize && !args.sixel => resize_img(img, args, size_cache)?,
+        (None, None) => img,
+        (width, height) => resize_img_to(img, width, height, args),
+    };
+    // Sharpened after resizing, so the detail that's brought out is what actually gets drawn
+    let sharpen = args.sharpen.clamp(0.0, MAX_SHARPEN);
+    Ok(if sharpen > 0.0 {
+        img.unsharpen(sharpen, 0)
+    } else {
+        img
+    })
+}
+
+/// Fail if an image is bigger than --max-pixels, so it's never rendered
+fn check_pixel_count((width, height): (u32, u32), args: &Args) -> Result<()> {
+    match u64::from(width).checked_mul(u64::from(height)) {
+        Some(pixels) if pixels <= args.max_pixels => Ok(()),
+        _ => Err(anyhow::anyhow!(TOO_MANY_PIXELS_ERR)),
+    }
+}
+
+fn print_img(
+    img: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    args: &Args,
+    out: &mut impl Write,
+    size_cache: Option<&SizeCache>,
+) -> Result<()> {
+    check_pixel_count(img.dimensions(), args)?;
+    let img = prepare_img(img, args, size_cache)?;
+    if args.print_size {
+        // A cell is one glyph, which can pack several pixels and span several characters
+        let rows = img.height().div_ceil(pixels_per_row(args));
+        eprintln!(
+            "{}x{} cells ({}x{} characters)",
+            img.width().div_ceil(pixels_per_column(args)),
+            rows,
+            output_columns(img.width(), args),
+            rows
+        );
+        return Ok(());
+    }
+    if args.sixel {
+        if !args.no_clear {
+            out.execute(cursor::MoveTo(0, 0))?;
+        }
+        queue!(out, Print(sixel(&img.to_rgb8())))?;
+        out.flush()?;
+        return Ok(());
+    }
+    if args.kitty || args.iterm {
+        if !args.no_clear {
+            out.execute(cursor::MoveTo(0, 0))?;
+        }
+        // Images resized to the cell grid are only as many pixels as cells, so have the
+        // terminal scale them back
+        let resized = args.resize || args.width.is_some() || args.height.is_some();
+        let cells = resized.then(|| {
+            (
+                output_columns(img.width(), args),
+                img.height().div_ceil(pixels_per_row(args)),
+            )
+        });
+        let img = img.to_rgb8();
+        let escape = if args.kitty {
+            kitty(&img, cells)?
+        } else {
+            iterm(&img, cells)?
+        };
+        queue!(out, Print(escape))?;
+        out.flush()?;
+        return Ok(());
+    }
+    let opts = args.render_options();
+    if let Some(path) = &args.output {
+        if path.extension().is_some_and(|e| e == "ans") {
+            return write_ansi(path, &ascii_rows(&img, &opts));
+        }
+        if opts.braille {
+            // Braille cells don't map onto single pixels, so files get the plain rendered text
+            let plain = RenderOptions {
+                colored: false,
+                background: None,
+                ..opts
+            };
+            std::fs::write(path, image_to_ascii(&img, &plain))?;
+            return Ok(());
+        }
+        let heat_map = heat_map(&opts);
+        let pixels_with_value = pixels_with_value(&img, &heat_map, &opts);
+        return match path.extension().and_then(|e| e.to_str()) {
+            Some("html") => write_html(path, &pixels_with_value, img.width(), &heat_map, &opts),
+            Some("json") => write_json(path, &pixels_with_value, img.width(), &heat_map),
+            Some("svg") => write_svg(path, &pixels_with_value, img.width(), &heat_map, &opts),
+            _ => write_img(path, &pixels_with_value, img.width(), &heat_map),
+        };
+    }
+    let columns = terminal_size().0 as u32;
+    let img = if output_columns(img.width(), args) <= columns {
+        img
+    } else if args.truncate {
+        // Only whole cells, so every line stops short of the edge
+        let width = columns / glyph_columns(args) * pixels_per_column(args);
+        img.crop_imm(0, 0, width, img.height())
+    } else {
+        LINES_WRAPPED.store(true, Ordering::Relaxed);
+        img
+    };
+    let rows = ascii_rows(&img, &opts);
+    let (left, top) = if args.center {
+        let (columns, lines) = terminal_size();
+        (
+            (columns as usize).saturating_sub(output_columns(img.width(), args) as usize) / 2,
+            (lines as usize).saturating_sub(rows.len()) / 2,
+        )
+    } else {
+        (0, 0)
+    };
+    if !args.no_clear {
+        out.execute(cursor::MoveTo(0, top as u16))?;
+    }
+    let padding = " ".repeat(left);
+    for row in rows {
+        queue!(out, Print(&padding), Print(row), Print(line_ending()))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Play a stream of frames, returning whether it already did the --hold (or was quit), since
+/// that has to happen before the terminal is restored
+fn print_stream<I>(stream: I, args: &Args, out: &mut impl Write) -> Result<bool>
+where
+    I: IntoIterator<Item = (image::ImageBuffer<image::Rgb<u8>, Vec<u8>>, Duration)>,
+{
+    // Sizing and previews only need one frame, and shouldn't take over the terminal
+    if let Some(index) = args.frame_index {
+        return match stream.into_iter().nth(index) {
+            Some((frame, _)) => print_img(frame, args, out, None).map(|()| false),
+            None => Err(anyhow::anyhow!(FRAME_INDEX_OUT_OF_RANGE_ERR)),
+        };
+    }
+    if args.print_size || args.first_frame {
+        return match stream.into_iter().next() {
+            Some((frame, _)) => print_img(frame, args, out, None).map(|()| false),
+            None => Ok(false),
+        };
+    }
+    let mut canvas_size = terminal_size();
+    let mut resized_at: Option<Instant> = None;
+    let size_cache = Arc::new(SizeCache::default());
+    let size_cache_callback = size_cache.clone();
+    let quit = AtomicBool::new(false);
+    let mut events = EventManager::default().append(
+        move || {
+            let size = terminal_size();
+            if canvas_size != size {
+                canvas_size = size;
+                resized_at = Some(Instant::now());
+            }
+            // Only clear once the size has settled, so dragging the window doesn't flash
+            if resized_at.is_some_and(|at| at.elapsed() >= RESIZE_DEBOUNCE) {
+                resized_at = None;
+                true
+            } else {
+                false
+            }
+        },
+        move || {
+            // The old frame is cleared away by play_stream, through `out`
+            *size_cache_callback.lock().unwrap() = None;
+        },
+    );
+
+    // The alternate screen has no scrollback, so appended frames stay on the main one
+    let mut guard = TerminalGuard::new(out, !args.no_clear)?;
+    // Pipes are kept open for the whole stream, since closing one ends it for the reader
+    let pipe = match &args.output {
+        Some(path) if is_fifo(path) => Some(BufWriter::new(
+            std::fs::OpenOptions::new().write(true).open(path)?,
+        )),
+        _ => None,
+    };
+    let stats = play_stream(
+        stream,
+        args,
+        &mut guard.out,
+        &mut events,
+        &quit,
+        &size_cache,
+        pipe,
+    )?;
+    if args.hold_last_frame {
+        while !quit.load(Ordering::Relaxed) && !quit_requested() {
+            sleep(HOLD_POLL_INTERVAL);
+        }
+    }
+    // Held here rather than at exit, since leaving the alternate screen takes the frame with it
+    if !quit.load(Ordering::Relaxed) {
+        hold(args, &mut guard.out)?;
+    }
+    // Restore the terminal first, so the summary isn't lost with the alternate screen
+    drop(guard);
+    if args.stats {
+        stats.report();
+    }
+    Ok(true)
+}
+
+/// Clear what was drawn for the old terminal size, which the next frame may not cover
+fn clear_after_resize(args: &Args, out: &mut impl Write) -> Result<()> {
+    if !args.no_clear {
+        execute!(out, Clear(ClearType::All))?;
+    }
+    Ok(())
+}
+
+/// Show a stream's frames in time, until it ends or q is pressed. Space pauses and resumes,
+/// and . steps through the frames while paused.
+fn play_stream<I>(
+    stream: I,
+    args: &Args,
+    out: &mut impl Write,
+    events: &mut EventManager<'_>,
+    quit: &AtomicBool,
+    size_cache: &SizeCache,
+    mut pipe: Option<BufWriter<File>>,
+) -> Result<StreamStats>
+where
+    I: IntoIterator<Item = (image::ImageBuffer<image::Rgb<u8>, Vec<u8>>, Duration)>,
+{
+    let mut frame_times = VecDeque::with_capacity(FPS_WINDOW);
+    let mut stats = StreamStats::default();
+    // Started along with the first frame, since that decides the video's size
+    let mut recorder: Option<Recorder> = None;
+    let start = Instant::now();
+    // When the current frame should go up, going by the delays of the frames so far
+    let mut due = start;
+    let mut paused = false;
+    let mut last_dimensions = None;
+    'frames: for (index, (frame, delay)) in stream
+        .into_iter()
+        .take(args.frames.unwrap_or(usize::MAX))
+        .enumerate()
+    {
+        let delay = frame_delay(args, delay);
+        if args.realtime && Instant::now() > due + delay {
+            // The next frame is already due, so drop this one to catch up
+            due += delay;
+            continue;
+        }
+        let rendering = Instant::now();
+        // A smaller frame wouldn't cover all of a bigger one before it, as slides and watched
+        // files can be. Frames are drawn at a size that only depends on theirs and the
+        // terminal's, and terminal resizes clear already.
+        let dimensions = frame.dimensions();
+        if last_dimensions.is_some_and(|last| last != dimensions)
+            && !args.no_clear
+            && pipe.is_none()
+        {
+            queue!(out, Clear(ClearType::All))?;
+        }
+        last_dimensions = Some(dimensions);
+        let average = args.color_bar.then(|| average_color(&frame));
+        if let Some(path) = &args.record {
+            let img = rasterize(&prepare_img(frame.clone(), args, Some(size_cache))?, args)?;
+            if recorder.is_none() {
+                recorder = Some(Recorder::spawn(path, img.dimensions(), delay)?);
+            }
+            recorder.as_mut().unwrap().write(&img)?;
+        }
+        match pipe.as_mut() {
+            Some(pipe) => write_to_pipe(frame, args, pipe, size_cache)?,
+            None => print_img(frame, args, out, Some(size_cache))?,
+        }
+        stats.render_times.push(rendering.elapsed());
+        stats.requested += delay;
+        if args.frame_numbers {
+            // On its own line below the frame, where the cursor is left
+            let label = format!(" frame {} at {:.1}s ", index, start.elapsed().as_secs_f64());
+            queue!(
+                out,
+                Clear(ClearType::CurrentLine),
+                PrintStyledContent(label.black().on_cyan()),
+                Print("\r\n")
+            )?;
+            out.flush()?;
+        }
+        if let Some(average) = average {
+            print_color_bar(average, args, out)?;
+        }
+        if args.show_fps {
+            if frame_times.len() == FPS_WINDOW {
+                frame_times.pop_front();
+            }
+            frame_times.push_back(Instant::now());
+            print_fps(&frame_times, args, out)?;
+        }
+        if events.run() {
+            clear_after_resize(args, out)?;
+        }
+        let controls = read_controls();
+        if controls.quit {
+            quit.store(true, Ordering::Relaxed);
+            break;
+        }
+        paused ^= controls.pause;
+        if paused {
+            // The frame stays up until playback resumes, or steps on to the next one
+            loop {
+                if events.run() {
+                    clear_after_resize(args, out)?;
+                }
+                let controls = read_controls();
+                if controls.quit {
+                    quit.store(true, Ordering::Relaxed);
+                    break 'frames;
+                }
+                if controls.pause || controls.step {
+                    paused = !controls.pause;
+                    break;
+                }
+                sleep(HOLD_POLL_INTERVAL);
+            }
+            // Time spent paused doesn't count towards the frames that come after
+            due = Instant::now();
+            continue;
+        }
+        due += delay;
+        if args.realtime {
+            sleep(due.saturating_duration_since(Instant::now()));
+        } else {
+            sleep(delay);
+        }
+    }
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+/// Draw an image's cells as text onto an image, the way a terminal would show them
+fn rasterize(img: &DynamicImage, args: &Args) -> Result<image::RgbImage> {
+    use ab_glyph::{Font, ScaleFont};
+
+    let opts = args.render_options();
+    let heat_map = heat_map(&opts);
+    let pixels_with_value = pixels_with_value(img, &heat_map, &opts);
+    let font = ab_glyph::FontRef::try_from_slice(RECORD_FONT)?;
+    let scale = ab_glyph::PxScale::from(RECORD_FONT_SIZE);
+    let scaled = font.as_scaled(scale);
+    let advance = scaled.h_advance(font.glyph_id(' '));
+    let line_height = scaled.height().ceil();
+    let cell_columns = opts.cell_width * glyph_width(&opts) + opts.cell_spacing;
+    let rows: Vec<_> = pixels_with_value
+        .chunks(img.width().max(1) as usize)
+        .collect();
+    let (width, height) = (
+        (advance * (cell_columns as u32 * img.width()) as f32).ceil() as u32,
+        (line_height * rows.len() as f32) as u32,
+    );
+    let (r, g, b) = opts.background.unwrap_or((0, 0, 0));
+    let mut out = image::RgbImage::from_pixel(width.max(1), height.max(1), image::Rgb([r, g, b]));
+    for (y, row) in rows.iter().enumerate() {
+        for (x, p) in row.iter().enumerate() {
+            let color = if opts.colored {
+                [p.0, p.1, p.2]
+            } else {
+                [255, 255, 255]
+            };
+            let left = advance * (x * cell_columns) as f32;
+            for (i, c) in glyph(p, &heat_map).chars().enumerate() {
+                let position = ab_glyph::point(
+                    left + advance * i as f32,
+                    line_height * y as f32 + scaled.ascent(),
+                );
+                let Some(outline) =
+                    font.outline_glyph(font.glyph_id(c).with_scale_and_position(scale, position))
+                else {
+                    continue;
+                };
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let (px, py) = (
+                        bounds.min.x as i32 + gx as i32,
+                        bounds.min.y as i32 + gy as i32,
+                    );
+                    if px < 0 || py < 0 || px as u32 >= width || py as u32 >= height {
+                        return;
+                    }
+                    let pixel = out.get_pixel_mut(px as u32, py as u32);
+                    for (channel, target) in pixel.0.iter_mut().zip(color) {
+                        *channel =
+                            (*channel as f32 + (target as f32 - *channel as f32) * coverage) as u8;
+                    }
+                });
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Encodes frames into a video with ffmpeg, which picks the format from the file extension.
+/// Dropping it closes ffmpeg's input and waits for it to finish the file.
+struct Recorder {
+    ffmpeg: std::process::Child,
+    size: (u32, u32),
+}
+
+impl Recorder {
+    /// Start ffmpeg for frames of the given size, played at the rate `delay` makes
+    fn spawn(path: &Path, size: (u32, u32), delay: Duration) -> Result<Self> {
+        let fps = 1.0 / delay.as_secs_f64().max(0.001);
+        let ffmpeg = std::process::Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgb24",
+            ])
+            .args(["-s", &format!("{}x{}", size.0, size.1)])
+            .args(["-r", &fps.to_string(), "-i", "-"])
+            .arg(path)
+            .stdin(std::process::Stdio::piped())
+            .spawn()?;
+        Ok(Recorder { ffmpeg, size })
+    }
+
+    /// Add a frame. Videos can't change size, so frames that don't match the first one are
+    /// cropped or padded to it.
+    fn write(&mut self, img: &image::RgbImage) -> Result<()> {
+        let mut frame = image::RgbImage::new(self.size.0, self.size.1);
+        image::imageops::replace(&mut frame, img, 0, 0);
+        self.ffmpeg
+            .stdin
+            .as_mut()
+            .context(RECORDER_CLOSED_ERR)?
+            .write_all(frame.as_raw())?;
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // ffmpeg only finishes the file once its input ends
+        drop(self.ffmpeg.stdin.take());
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+/// The mean color of all of an image's pixels
+fn average_color(img: &image::RgbImage) -> (u8, u8, u8) {
+    let mut sum = [0u64; 3];
+    for p in img.pixels() {
+        for (total, c) in sum.iter_mut().zip(p.0) {
+            *total += c as u64;
+        }
+    }
+    let count = (img.width() as u64 * img.height() as u64).max(1);
+    let [r, g, b] = sum.map(|total| (total / count) as u8);
+    (r, g, b)
+}
+
+/// Draw a full width bar in the given color on the bottom line, or below the frame when frames
+/// are appended
+fn print_color_bar(color: (u8, u8, u8), args: &Args, out: &mut impl Write) -> Result<()> {
+    let (columns, lines) = terminal_size();
+    let bar = " "
+        .repeat(columns as usize)
+        .on(terminal_color(color, args.color_mode));
+    if args.no_clear {
+        queue!(out, PrintStyledContent(bar), Print("\r\n"))?;
+    } else {
+        queue!(
+            out,
+            cursor::MoveTo(0, lines.saturating_sub(1)),
+            PrintStyledContent(bar)
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Frame timings gathered over a stream, for --stats
+#[derive(Default)]
+struct StreamStats {
+    /// How long each shown frame took to render
+    render_times: Vec<Duration>,
+    /// The total of the delays asked for by the shown frames
+    requested: Duration,
+    /// Wall time from the first frame to the end of the stream
+    elapsed: Duration,
+}
+
+impl StreamStats {
+    fn report(&self) {
+        let frames = self.render_times.len();
+        eprintln!("Frames: {}", frames);
+        if frames == 0 {
+            return;
+        }
+        let total: Duration = self.render_times.iter().sum();
+        let min = self.render_times.iter().min().unwrap();
+        let max = self.render_times.iter().max().unwrap();
+        let per_second = |time: Duration| match time.as_secs_f64() {
+            secs if secs > 0.0 => frames as f64 / secs,
+            _ => 0.0,
+        };
+        eprintln!(
+            "Render time: {:.1}ms average, {:.1}ms min, {:.1}ms max",
+            total.as_secs_f64() * 1000.0 / frames as f64,
+            min.as_secs_f64() * 1000.0,
+            max.as_secs_f64() * 1000.0
+        );
+        eprintln!(
+            "FPS: {:.1} achieved, {:.1} requested",
+            per_second(self.elapsed),
+            per_second(self.requested)
+        );
+    }
+}
+
+/// Write a frame's plain ASCII art to a pipe, followed by the frame separator
+fn write_to_pipe(
+    frame: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    args: &Args,
+    pipe: &mut impl Write,
+    size_cache: &SizeCache,
+) -> Result<()> {
+    let img = prepare_img(frame, args, Some(size_cache))?;
+    let opts = RenderOptions {
+        colored: false,
+        background: None,
+        ..args.render_options()
+    };
+    pipe.write_all(image_to_ascii(&img, &opts).as_bytes())?;
+    pipe.write_all(args.frame_separator.as_bytes())?;
+    pipe.flush()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
+/// Draw the average frame rate over `frame_times` on the top line, or below the frame when
+/// frames are appended
+fn print_fps(frame_times: &VecDeque<Instant>, args: &Args, out: &mut impl Write) -> Result<()> {
+    let (Some(first), Some(last)) = (frame_times.front(), frame_times.back()) else {
+        return Ok(());
+    };
+    let elapsed = last.duration_since(*first).as_secs_f64();
+    let fps = if elapsed > 0.0 {
+        (frame_times.len() - 1) as f64 / elapsed
+    } else {
+        0.0
+    };
+    let label = format!(" {:.1} fps ", fps).black().on_yellow();
+    if args.no_clear {
+        queue!(out, PrintStyledContent(label), Print("\r\n"))?;
+    } else {
+        queue!(out, cursor::MoveTo(0, 0), PrintStyledContent(label))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// How long to show a frame for, given the delay the source asked for
+fn frame_delay(args: &Args, delay: Duration) -> Duration {
+    match (args.fps, args.animation_delay) {
+        (Some(fps), _) => Duration::from_secs_f64(1.0 / fps),
+        (None, Some(animation_delay)) => Duration::from_millis(animation_delay),
+        (None, None) => delay,
+    }
+}
+
+/// Keep what's on screen for --hold milliseconds, or until a key is pressed if it's 0
+fn hold(args: &Args, out: &mut impl Write) -> Result<()> {
+    match args.hold {
+        Some(0) => {
+            // Raw mode, so any key will do rather than only Enter
+            let _guard = TerminalGuard::new(out, false)?;
+            while !matches!(event::read()?, Event::Key(_)) {}
+        }
+        Some(millis) => sleep(Duration::from_millis(millis)),
+        None => (),
+    }
+    Ok(())
+}
+
+/// What the keys pressed while a stream plays ask for
+#[derive(Default)]
+struct Controls {
+    /// q, Esc or Ctrl-C
+    quit: bool,
+    /// Space, to pause or resume
+    pause: bool,
+    /// ., to show the next frame while paused
+    step: bool,
+}
+
+/// Drain pending terminal events, collecting what their keys asked for
+fn read_controls() -> Controls {
+    let mut controls = Controls::default();
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        if let Ok(Event::Key(key)) = event::read() {
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => controls.quit = true,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    controls.quit = true
+                }
+                // Pressing it twice before it's seen cancels out
+                KeyCode::Char(' ') => controls.pause = !controls.pause,
+                KeyCode::Char('.') => controls.step = true,
+                _ => (),
+            }
+        }
+    }
+    controls
+}
+
+/// Drain pending terminal events, returning whether q, Esc or Ctrl-C was pressed
+fn quit_requested() -> bool {
+    read_controls().quit
+}
+
+fn animation_frame_delay(frame: &Frame) -> Duration {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    let delay = Duration::from_millis((numer / denom.max(1)) as u64);
+    if delay.is_zero() {
+        ZERO_FRAME_DELAY_FALLBACK
+    } else {
+        delay
+    }
+}
+
+fn print_gif<P>(path: P, args: &Args) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    // The image crate doesn't expose the Netscape looping extension, so peek at it with gif.
+    // It comes after the header, so it's only been read once the first frame's info has.
+    let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path)?)?;
+    decoder.next_frame_info()?;
+    let plays = match decoder.repeat() {
+        gif::Repeat::Infinite => None,
+        // The count is of repetitions, after the first play
+        gif::Repeat::Finite(count) => Some(count as u32 + 1),
+    };
+    let file = std::fs::File::open(path)?;
+    print_animation(
+        GifDecoder::new(file)?.into_frames().collect_frames()?,
+        plays,
+        args,
+    )
+}
+
+/// Play APNGs like GIFs, and everything else with a single frame as a still
+fn print_png<P>(path: P, args: &Args) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    // The image crate doesn't expose the APNG play count either, so peek at it with png
+    let animation = png::Decoder::new(File::open(&path)?)
+        .read_info()?
+        .info()
+        .animation_control;
+    match animation {
+        Some(animation) if animation.num_frames > 1 => {
+            let frames = PngDecoder::new(File::open(&path)?)?
+                .apng()
+                .into_frames()
+                .collect_frames()?;
+            // Zero plays means forever
+            let plays = Some(animation.num_plays).filter(|plays| *plays > 0);
+            print_animation(frames, plays, args)
+        }
+        _ => print_img(
+            ImageReader::open(&path)?.decode()?.to_rgb8(),
+            args,
+            &mut stdout(),
+            None,
+        )
+        .map(|()| false),
+    }
+}
+
+/// Play animated WebPs like GIFs, and still ones as stills
+fn print_webp<P>(path: P, args: &Args) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let decoder = WebPDecoder::new(File::open(&path)?)?;
+    if !decoder.has_animation() {
+        return print_img(
+            DynamicImage::from_decoder(decoder)?.to_rgb8(),
+            args,
+            &mut stdout(),
+            None,
+        )
+        .map(|()| false);
+    }
+    let plays = webp_loop_count(&std::fs::read(&path)?);
+    print_animation(decoder.into_frames().collect_frames()?, plays, args)
+}
+
+/// The loop count in an animated WebP's ANIM chunk, which the image crate doesn't expose. Zero
+/// (or no chunk) means forever.
+fn webp_loop_count(bytes: &[u8]) -> Option<u32> {
+    // Chunks follow the 12 byte RIFF header, each an ID, a little endian size and the payload,
+    // padded to an even length
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let payload = offset + 8;
+        if &bytes[offset..offset + 4] == b"ANIM" {
+            let count = bytes.get(payload + 4..payload + 6)?;
+            return Some(u16::from_le_bytes([count[0], count[1]]) as u32).filter(|n| *n > 0);
+        }
+        offset = payload + size + size % 2;
+    }
+    None
+}
+
+/// Stream decoded animation frames. With --loop-animation they play `plays` times, or forever
+/// if that's None, and --loop-count overrides both.
+fn print_animation(frames: Vec<Frame>, plays: Option<u32>, args: &Args) -> Result<bool> {
+    let frames = frames.into_iter().map(|f| {
+        let delay = animation_frame_delay(&f);
+        (f.into_buffer().convert(), delay)
+    });
+    let plays = match (args.loop_count, args.loop_animation) {
+        (Some(count), _) => Some(count),
+        (None, true) => plays.map(|plays| plays.max(1) as usize),
+        (None, false) => Some(1),
+    };
+    let loop_delay = Duration::from_millis(args.loop_delay.unwrap_or(0));
+    let loops = std::iter::repeat(frames)
+        .take(plays.unwrap_or(usize::MAX))
+        .enumerate()
+        .flat_map(move |(index, frames)| {
+            // Asking for the first frame of every loop but the first waits out the pause
+            let pause = std::iter::from_fn(move || {
+                if index > 0 {
+                    sleep(loop_delay);
+                }
+                None
+            });
+            pause.chain(frames)
+        });
+    print_stream(loops, args, &mut stdout())
+}
+
+fn print_camera(args: &Args) -> Result<bool> {
+    let mut camera =
+        CameraIter::try_new(args.camera_index, args.camera_resolution, args.camera_fps)?;
+    if args.loop_animation {
+        print_stream(
+            camera.map(|f| (f, DEFAULT_FRAME_DELAY)),
+            args,
+            &mut stdout(),
+        )
+    } else {
+        print_img(
+            camera.next().context("Failed to get frame from camera")?,
+            args,
+            &mut stdout(),
+            None,
+        )
+        .map(|()| false)
+    }
+}
+
+/// Frames decoded by an ffmpeg process, which is killed once they're dropped
+struct ClipFrames {
+    ffmpeg: std::process::Child,
+    frames: std::io::BufReader<std::process::ChildStdout>,
+}
+
+impl Iterator for ClipFrames {
+    type Item = image::RgbImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Each frame is a PPM: P6, its width, height and maximum value, each followed by a
+        // whitespace byte, then the pixels
+        let mut header = Vec::with_capacity(4);
+        let mut field = Vec::new();
+        while header.len() < 4 {
+            let mut byte = [0];
+            self.frames.read_exact(&mut byte).ok()?;
+            if !byte[0].is_ascii_whitespace() {
+                field.push(byte[0]);
+            } else if !field.is_empty() {
+                header.push(String::from_utf8(std::mem::take(&mut field)).ok()?);
+            }
+        }
+        let width = header[1].parse().ok()?;
+        let height = header[2].parse().ok()?;
+        let mut pixels = vec![0; width as usize * height as usize * 3];
+        self.frames.read_exact(&mut pixels).ok()?;
+        image::RgbImage::from_raw(width, height, pixels)
+    }
+}
+
+impl Drop for ClipFrames {
+    fn drop(&mut self) {
+        let _ = self.ffmpeg.kill();
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+/// Decode the part of a video selected by --start and --duration. ffmpeg does the seeking, which
+/// lands on the exact frame since it's decoding anyway, and skips straight to the start.
+fn clip_frames(args: &Args, source: &Path) -> Result<ClipFrames> {
+    let mut ffmpeg = std::process::Command::new("ffmpeg");
+    // Kept off stdin, which ffmpeg would otherwise take keypresses from
+    ffmpeg.args(["-nostdin", "-loglevel", "error"]);
+    if let Some(start) = args.start {
+        ffmpeg.arg("-ss").arg(format!("{:.3}", start.as_secs_f64()));
+    }
+    ffmpeg.arg("-i").arg(source);
+    if let Some(duration) = args.duration {
+        ffmpeg
+            .arg("-t")
+            .arg(format!("{:.3}", duration.as_secs_f64()));
+    }
+    // PPMs carry their own dimensions, which rotated videos don't share with their streams
+    let mut child = ffmpeg
+        .args(["-an", "-f", "image2pipe", "-c:v", "ppm", "pipe:1"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .context(FFMPEG_CLIP_ERR)?;
+    let frames = std::io::BufReader::new(child.stdout.take().context(FFMPEG_CLIP_ERR)?);
+    Ok(ClipFrames {
+        ffmpeg: child,
+        frames,
+    })
+}
+
+/// Stream a video through ffmpeg. `source` can be anything ffmpeg accepts as an input, local
+/// files as well as network streams.
+fn print_video(args: &Args, source: PathBuf) -> Result<bool> {
+    let delay = video_frame_delay(&source).unwrap_or(DEFAULT_FRAME_DELAY);
+    let frames: Box<dyn Iterator<Item = image::RgbImage>> =
+        if args.start.is_some() || args.duration.is_some() {
+            Box::new(clip_frames(args, &source)?)
+        } else {
+            Box::new(
+                ffmpeg_cmdline_utils::FfmpegFrameReaderBuilder::new(source.clone())
+                    .spawn()?
+                    .0,
+            )
+        };
+    let frames = frames.map(move |f| (f, delay));
+    let _audio = if args.audio && !args.first_frame && args.frame_index.is_none() {
+        play_audio(args, &source)
+    } else {
+        None
+    };
+    print_stream(frames, args, &mut stdout())
+}
+
+/// How long each frame of a video is meant to be shown for, going by its frame rate
+fn video_frame_delay(source: &Path) -> Option<Duration> {
+    let output = std::process::Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=r_frame_rate", "-of", "csv=p=0"])
+        .arg(source)
+        .output()
+        .ok()?;
+    // Rates are fractions, like 30000/1001
+    let rate = String::from_utf8_lossy(&output.stdout);
+    let (numerator, denominator) = rate.trim().split_once('/')?;
+    let fps = numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?;
+    (fps.is_finite() && fps > 0.0).then(|| Duration::from_secs_f64(1.0 / fps))
+}
+
+/// Kills the audio player once dropped, so the audio stops along with the video
+struct AudioPlayer(std::process::Child);
+
+impl Drop for AudioPlayer {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn has_audio(source: &Path) -> bool {
+    std::process::Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "a"])
+        .args(["-show_entries", "stream=index", "-of", "csv=p=0"])
+        .arg(source)
+        .output()
+        .map(|output| output.status.success() && !output.stdout.is_empty())
+        .unwrap_or(false)
+}
+
+/// Play the audio track of `source` with ffplay in the background, if it has one
+fn play_audio(args: &Args, source: &Path) -> Option<AudioPlayer> {
+    if !has_audio(source) {
+        return None;
+    }
+    let mut ffplay = std::process::Command::new("ffplay");
+    ffplay.args(["-nodisp", "-autoexit", "-loglevel", "quiet"]);
+    if let Some(start) = args.start {
+        ffplay.arg("-ss").arg(format!("{:.3}", start.as_secs_f64()));
+    }
+    if let Some(duration) = args.duration {
+        ffplay
+            .arg("-t")
+            .arg(format!("{:.3}", duration.as_secs_f64()));
+    }
+    let player = ffplay
+        .arg(source)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    match player {
+        Ok(child) => Some(AudioPlayer(child)),
+        Err(_) => {
+            if !args.quiet {
+                eprintln!("{}", NO_FFPLAY_WARNING);
+            }
+            None
+        }
+    }
+}
+
+/// Describe the input `name`, whose contents are at `path`, on stderr, leaving out whatever
+/// can't be read from it
+fn print_info(name: &str, path: &Path) {
+    let Ok(format) = FileFormat::from_file(path) else {
+        return;
+    };
+    eprintln!("{}", name);
+    eprintln!("  Format: {}", format.name());
+    if let Ok(metadata) = std::fs::metadata(path) {
+        eprintln!("  File size: {} bytes", metadata.len());
+    }
+    match format {
+        FileFormat::Mpeg4Part14Video | FileFormat::MatroskaVideo => {
+            let probe = std::process::Command::new("ffprobe")
+                .args(["-v", "error", "-select_streams", "v:0", "-count_packets"])
+                .args([
+                    "-show_entries",
+                    "stream=width,height,nb_read_packets:format=duration",
+                ])
+                .args(["-of", "default=noprint_wrappers=1"])
+                .arg(path)
+                .output();
+            let Ok(probe) = probe else {
+                return;
+            };
+            let probe = String::from_utf8_lossy(&probe.stdout);
+            let field = |key: &str| {
+                probe
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                    .map(str::to_string)
+            };
+            if let (Some(width), Some(height)) = (field("width"), field("height")) {
+                eprintln!("  Dimensions: {}x{}", width, height);
+            }
+            if let Some(frames) = field("nb_read_packets") {
+                eprintln!("  Frames: {}", frames);
+            }
+            if let Some(duration) = field("duration").and_then(|d| d.parse::<f64>().ok()) {
+                eprintln!("  Duration: {:.2}s", duration);
+            }
+        }
+        _ => {
+            if let Ok((width, height)) = image::image_dimensions(path) {
+                eprintln!("  Dimensions: {}x{}", width, height);
+            }
+            let Ok(Some(frames)) = animation_frames(path, format) else {
+                return;
+            };
+            let duration: Duration = frames.iter().map(animation_frame_delay).sum();
+            eprintln!("  Frames: {}", frames.len());
+            eprintln!("  Duration: {:.2}s", duration.as_secs_f64());
+        }
+    }
+}
+
+/// Every frame of an animated GIF, PNG or WebP, or None for stills
+fn animation_frames(path: &Path, format: FileFormat) -> Result<Option<Vec<Frame>>> {
+    let frames = match format {
+        FileFormat::GraphicsInterchangeFormat => GifDecoder::new(File::open(path)?)?.into_frames(),
+        FileFormat::PortableNetworkGraphics => {
+            let decoder = PngDecoder::new(File::open(path)?)?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        }
+        FileFormat::Webp => {
+            let decoder = WebPDecoder::new(File::open(path)?)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frames.collect_frames()?))
+}
+
+/// Whether a URL points at a live stream, which never finishes downloading and so has to be
+/// handed to ffmpeg instead of being buffered
+fn is_live_stream(url: &reqwest::Url) -> bool {
+    match url.scheme() {
+        "rtsp" => true,
+        "http" | "https" => url.path().ends_with(".m3u8"),
+        _ => false,
+    }
+}
+
+fn handle_fs_path<P>(args: &Args, path: P) -> Result<bool>
+where
+    P: Into<PathBuf>,
+{
+    let path: PathBuf = path.into();
+    match FileFormat::from_file(&path)? {
+        FileFormat::Mpeg4Part14Video | FileFormat::MatroskaVideo => print_video(args, path),
+        FileFormat::Av1ImageFileFormat if !cfg!(feature = "avif") => {
+            Err(anyhow::anyhow!(NO_AVIF_ERR))
+        }
+        FileFormat::PortableNetworkGraphics => print_png(path, args),
+        FileFormat::Webp => print_webp(path, args),
+        FileFormat::JointPhotographicExpertsGroup
+        | FileFormat::WindowsBitmap
+        | FileFormat::Av1ImageFileFormat
+        | FileFormat::TagImageFileFormat => {
+            // Only the header is read for this, so oversized images are never decoded
+            check_pixel_count(image::image_dimensions(&path)?, args)?;
+            print_img(
+                ImageReader::open(&path)?.decode()?.to_rgb8(),
+                args,
+                &mut stdout(),
+                None,
+            )
+            .map(|()| false)
+        }
+        FileFormat::GraphicsInterchangeFormat => print_gif(path, args),
+        // Let the image crate have a go at anything else
+        _ => {
+            let reader = ImageReader::open(&path)?.with_guessed_format()?;
+            if reader.format().is_none() {
+                return Err(anyhow::anyhow!(UNEXPECTED_FILE_TYPE_ERR));
+            }
+            print_img(
+                reader.decode().context(UNEXPECTED_FILE_TYPE_ERR)?.to_rgb8(),
+                args,
+                &mut stdout(),
+                None,
+            )
+            .map(|()| false)
+        }
+    }
+}
+
+/// Show every image in a directory in name order, each for --animation-delay milliseconds
+fn print_slideshow<P>(args: &Args, dir: P) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && image::ImageFormat::from_path(&path).is_ok() {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!(EMPTY_DIRECTORY_ERR));
+    }
+    paths.sort();
+    let count = if args.loop_animation {
+        usize::MAX
+    } else {
+        paths.len()
+    };
+    // Images are decoded as they come up, and ones that fail to decode are skipped
+    let slides = paths.iter().cycle().take(count).filter_map(|path| {
+        let img = ImageReader::open(path).ok()?.decode().ok()?;
+        Some((img.to_rgb8(), DEFAULT_SLIDE_DELAY))
+    });
+    print_stream(slides, args, &mut stdout())
+}
+
+fn print_clipboard(args: &Args) -> Result<()> {
+    let image = arboard::Clipboard::new()?
+        .get_image()
+        .context(NO_CLIPBOARD_IMAGE_ERR)?;
+    let img = image::RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    .context(NO_CLIPBOARD_IMAGE_ERR)?;
+    print_img(img.convert(), args, &mut stdout(), None)
+}
+
+/// Re-render an image whenever it's written to, until the user quits
+fn watch_file<P>(args: &Args, path: P) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().canonicalize()?;
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    // Editors often replace files rather than writing to them, so watch the directory instead
+    watcher.watch(
+        path.parent().unwrap_or_else(|| Path::new(".")),
+        notify::RecursiveMode::NonRecursive,
+    )?;
+    let renders = std::iter::once(())
+        .chain(std::iter::from_fn(|| wait_for_change(&receiver, &path)))
+        .filter_map(|()| {
+            let img = ImageReader::open(&path)
+                .ok()?
+                .with_guessed_format()
+                .ok()?
+                .decode()
+                .ok()?;
+            Some((img.to_rgb8(), Duration::ZERO))
+        });
+    print_stream(renders, args, &mut stdout())
+}
+
+/// Block until `path` is modified, or return None once the user asks to quit
+fn wait_for_change(
+    receiver: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    path: &Path,
+) -> Option<()> {
+    use std::sync::mpsc::RecvTimeoutError;
+    loop {
+        match receiver.recv_timeout(HOLD_POLL_INTERVAL) {
+            Ok(Ok(event))
+                if !event.kind.is_access() && event.paths.iter().any(|changed| changed == path) =>
+            {
+                break
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {
+                if quit_requested() {
+                    return None;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    // Let a burst of writes settle, so a file being rewritten is only rendered once it's done
+    while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    Some(())
+}
+
+/// Request a URL, retrying on failures that might go away by themselves. Client errors won't,
+/// so they fail straight away.
+fn fetch(
+    args: &Args,
+    client: &reqwest::blocking::Client,
+    url: reqwest::Url,
+) -> Result<reqwest::blocking::Response> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match client
+            .get(url.clone())
+            .send()
+            .and_then(|r| r.error_for_status())
+        {
+            Err(err) if attempt < args.retries && is_transient(&err) => {
+                if !args.quiet {
+                    eprintln!("{}, retrying in {:.1}s", err, backoff.as_secs_f64());
+                }
+                sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// The client URLs are fetched with, which gives up after --timeout and names itself, since
+/// some servers turn away requests without a user agent
+fn http_client(args: &Args) -> Result<reqwest::blocking::Client> {
+    Ok(reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(args.timeout))
+        .user_agent(concat!("image2ascii/", env!("CARGO_PKG_VERSION")))
+        .build()?)
+}
+
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err.status().is_some_and(|status| status.is_server_error())
+}
+
+/// Fetch a URL into memory, with a progress line on stderr while it downloads
+fn download(args: &Args, client: &reqwest::blocking::Client, url: reqwest::Url) -> Result<Vec<u8>> {
+    let mut response = fetch(args, client, url)?;
+    let total = response.content_length();
+    let mut bytes = Vec::with_capacity(total.unwrap_or(0) as usize);
+    let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+    let mut last_progress = None;
+    loop {
+        let read = response.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        if args.quiet {
+            continue;
+        }
+        let progress = match total {
+            Some(total) if total > 0 => format!("{}%", bytes.len() as u64 * 100 / total),
+            _ => format!("{} KiB", bytes.len() / 1024),
+        };
+        // Only redraw when the line would change
+        if last_progress.as_ref() != Some(&progress) {
+            eprint!("\rDownloading... {}", progress);
+            last_progress = Some(progress);
+        }
+    }
+    if last_progress.is_some() {
+        execute!(
+            std::io::stderr(),
+            Clear(ClearType::CurrentLine),
+            cursor::MoveToColumn(0)
+        )?;
+    }
+    Ok(bytes)
+}
+
+/// Render raw pixels, a byte per channel with rows running top to bottom, skipping format
+/// detection
+fn print_raw(args: &Args, bytes: Vec<u8>, width: u32, height: u32, channels: u8) -> Result<()> {
+    let expected = u64::from(width) * u64::from(height) * u64::from(channels);
+    if bytes.len() as u64 != expected {
+        return Err(anyhow::anyhow!(RAW_SIZE_MISMATCH_ERR));
+    }
+    // The length was just checked, so none of these can fail
+    let img = match channels {
+        1 => DynamicImage::ImageLuma8(image::GrayImage::from_raw(width, height, bytes).unwrap()),
+        3 => DynamicImage::ImageRgb8(image::RgbImage::from_raw(width, height, bytes).unwrap()),
+        _ => DynamicImage::ImageRgba8(image::RgbaImage::from_raw(width, height, bytes).unwrap()),
+    };
+    print_img(img.to_rgb8(), args, &mut stdout(), None)
+}
+
+/// Render in-memory media. Stills are decoded directly, while GIFs, PNGs and WebPs (which may be
+/// animated) and videos go through a temporary file since their paths read from disk, as does
+/// everything for --info. `name` is what the bytes are called there.
+fn handle_bytes(args: &Args, name: &str, bytes: &[u8]) -> Result<bool> {
+    let format = FileFormat::from_bytes(bytes);
+    let from_disk = matches!(
+        format,
+        FileFormat::Mpeg4Part14Video
+            | FileFormat::MatroskaVideo
+            | FileFormat::GraphicsInterchangeFormat
+            | FileFormat::PortableNetworkGraphics
+            | FileFormat::Webp
+    );
+    if from_disk || args.info {
+        let mut file = tempfile::Builder::new()
+            .suffix((String::from(".") + format.extension()).as_str())
+            .tempfile()?;
+        file.as_file_mut().write_all(bytes)?;
+        if args.info {
+            print_info(name, file.path());
+        }
+        if from_disk {
+            return handle_fs_path(args, file.path());
+        }
+    }
+    match format {
+        FileFormat::Av1ImageFileFormat if !cfg!(feature = "avif") => {
+            Err(anyhow::anyhow!(NO_AVIF_ERR))
+        }
+        _ => print_img(
+            image::load_from_memory(bytes)
+                .context(UNEXPECTED_FILE_TYPE_ERR)?
+                .to_rgb8(),
+            args,
+            &mut stdout(),
+            None,
+        )
+        .map(|()| false),
+    }
+}
+
+/// Best guess at whether the terminal understands the kitty graphics protocol
+fn supports_kitty() -> bool {
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || std::env::var("TERM").unwrap_or_default().contains("kitty")
+}
+
+/// Whether the console expects UTF-8, going by its output code page
+#[cfg(windows)]
+fn terminal_is_utf8() -> bool {
+    const UTF8_CODE_PAGE: u32 = 65001;
+    unsafe { winapi::um::consoleapi::GetConsoleOutputCP() == UTF8_CODE_PAGE }
+}
+
+/// Whether the terminal expects UTF-8, going by the locale. Without one, it probably does.
+#[cfg(not(windows))]
+fn terminal_is_utf8() -> bool {
+    // The first of these that's set decides the character set
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        None => true,
+    }
+}
+
+/// Whether the terminal is iTerm2, which names itself in $TERM_PROGRAM
+fn is_iterm() -> bool {
+    std::env::var("TERM_PROGRAM").is_ok_and(|program| program == "iTerm.app")
+}
+
+/// Best guess at whether the terminal can display 24-bit color
+fn supports_truecolor() -> bool {
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+    ["truecolor", "24bit"].iter().any(|v| colorterm.contains(v))
+        || ["truecolor", "24bit", "direct"]
+            .iter()
+            .any(|v| term.contains(v))
+}
+
+/// Show every input, returning whether the last one already did the --hold
+fn run(args: &Args) -> Result<bool> {
+    if args.webcam_feed {
+        return print_camera(args);
+    }
+    if args.clipboard {
+        return print_clipboard(args).map(|()| false);
+    }
+    // Clap requires a path or a list unless one of the above is set
+    let mut paths = args.file_path.clone();
+    if let Some(list) = &args.input_list {
+        paths.extend(read_input_list(list)?);
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!(EMPTY_INPUT_LIST_ERR));
+        }
+    }
+    if let [path] = paths.as_slice() {
+        return run_path(args, path);
+    }
+    // Every input would overwrite the one before it
+    if args.output.is_some() {
+        return Err(anyhow::anyhow!(MULTIPLE_OUTPUT_INPUTS_ERR));
+    }
+    // One bad input shouldn't stop the rest of the batch, so failures are reported at the end
+    let mut failures = Vec::new();
+    let mut held = false;
+    for (index, path) in paths.iter().enumerate() {
+        // Sizes are only printed, so there's nothing to give time on screen or clear away
+        if index > 0 && !args.print_size {
+            // Give every input the same time on screen as a slideshow's images get
+            sleep(frame_delay(args, DEFAULT_SLIDE_DELAY));
+            if !args.no_clear {
+                execute!(stdout(), Clear(ClearType::All))?;
+            }
+        }
+        match run_path(args, path) {
+            Ok(input_held) => held = input_held,
+            Err(err) => failures.push((path, err)),
+        }
+    }
+    for (path, err) in &failures {
+        eprintln!("{}: {}", path, err);
+    }
+    if failures.is_empty() {
+        Ok(held)
+    } else {
+        Err(anyhow::anyhow!(
+            "{} of {} inputs couldn't be shown",
+            failures.len(),
+            paths.len()
+        ))
+    }
+}
+
+/// The paths and URLs in an --input-list file
+fn read_input_list(list: &Path) -> Result<Vec<String>> {
+    Ok(std::fs::read_to_string(list)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Show a single input, picking how by what the path turns out to be
+fn run_path(args: &Args, path: &str) -> Result<bool> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        stdin().lock().read_to_end(&mut bytes)?;
+        match args.raw {
+            Some((width, height, channels)) => {
+                print_raw(args, bytes, width, height, channels).map(|()| false)
+            }
+            None => handle_bytes(args, "stdin", &bytes),
+        }
+    } else if std::path::Path::new(path).is_dir() {
+        print_slideshow(args, path)
+    } else if std::path::Path::new(path).is_file() {
+        if args.info {
+            print_info(path, Path::new(path));
+        }
+        if args.watch {
+            return watch_file(args, path);
+        }
+        handle_fs_path(args, path)
+    } else if let Ok(url) = reqwest::Url::parse(path) {
+        if is_live_stream(&url) {
+            return print_video(args, PathBuf::from(url.as_str()));
+        }
+        let client = http_client(args)?;
+        handle_bytes(args, path, &download(args, &client, url)?)
     } else {
         Err(anyhow::anyhow!(INVALID_URI_ERR))
     }
 }
 
+/// Restore the terminal before the default hook prints the panic. Drop doesn't run on panic in
+/// release builds, which abort, so `TerminalGuard` can't be relied on there.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Undoing what was never done is harmless, so there's no need to track what was
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(stdout(), terminal::LeaveAlternateScreen, cursor::Show);
+        default_hook(info);
+    }));
+}
+
+fn main() -> Result<()> {
+    install_panic_hook();
+    let mut args = Args::parse();
+    if args.fit_scroll {
+        args.resize = true;
+        args.fit = FitMode::Width;
+        args.no_clear = true;
+    }
+    // Files are always written as UTF-8, so only terminals are worth checking
+    args.ascii_only |= match args.encoding {
+        Encoding::Auto => args.output.is_none() && !terminal_is_utf8(),
+        Encoding::Ascii => true,
+        Encoding::Utf8 => false,
+    };
+    // Older Windows consoles only understand escape sequences once asked to
+    #[cfg(windows)]
+    let _ = crossterm::ansi_support::supports_ansi();
+    if args.output.is_none() && !args.no_clear && !args.print_size && !args.quiet {
+        execute!(stdout(), Clear(ClearType::All)).unwrap();
+    }
+    // Hidden for the whole run, so it doesn't blink over the art, and shown again however the
+    // run ends. Captured output shouldn't get the escape sequences, though.
+    let hide_cursor = args.output.is_none()
+        && !args.print_size
+        && !args.keep_cursor
+        && !args.quiet
+        && stdout().is_terminal();
+    if hide_cursor {
+        execute!(stdout(), cursor::Hide)?;
+    }
+    let result = match run(&args) {
+        Ok(false) => hold(&args, &mut stdout()),
+        result => result.map(|_| ()),
+    };
+    if hide_cursor {
+        let _ = execute!(stdout(), cursor::Show);
+    }
+    // On stderr, so it stays out of anything stdout is piped into
+    if args.bell && !args.quiet {
+        eprint!("\x07");
+    }
+    // Warn after rendering, since the initial clear would wipe it otherwise
+    if !args.quiet
+        && args.use_color()
+        && args.color_mode == ColorMode::TrueColor
+        && args.output.is_none()
+        && !supports_truecolor()
+    {
+        eprintln!("{}", NO_TRUECOLOR_WARNING);
+    }
+    if !args.quiet && LINES_WRAPPED.load(Ordering::Relaxed) {
+        eprintln!("{}", LINES_WRAPPED_WARNING);
+    }
+    if !args.quiet && args.kitty && args.output.is_none() && !supports_kitty() {
+        eprintln!("{}", NO_KITTY_WARNING);
+    }
+    if !args.quiet && args.iterm && args.output.is_none() && !is_iterm() {
+        eprintln!("{}", NO_ITERM_WARNING);
+    }
+    if !args.quiet && args.charset.is_some() && glyph_width(&args.render_options()) > 1 {
+        eprintln!("{}", WIDE_CHARSET_WARNING);
+    }
+    result
+}
+
 struct CameraIter {
     camera: nokhwa::Camera,
 }
 
-impl Default for CameraIter {
-    fn default() -> Self {
-        let index = nokhwa::utils::CameraIndex::Index(0);
-        let requested = nokhwa::utils::RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(
-            nokhwa::utils::RequestedFormatType::AbsoluteHighestFrameRate,
-        );
-        let mut camera = nokhwa::Camera::new(index, requested).unwrap();
+impl CameraIter {
+    /// Open a camera, at the closest format to the given resolution and frame rate. Without
+    /// either, the highest frame rate the camera offers is used.
+    fn try_new(index: u32, resolution: Option<(u32, u32)>, fps: Option<u32>) -> Result<Self> {
+        use nokhwa::utils::{CameraFormat, FrameFormat, RequestedFormatType, Resolution};
+        let format_type =
+            match (resolution, fps) {
+                (Some((width, height)), Some(fps)) => RequestedFormatType::Closest(
+                    CameraFormat::new(Resolution::new(width, height), FrameFormat::MJPEG, fps),
+                ),
+                (Some((width, height)), None) => {
+                    RequestedFormatType::HighestResolution(Resolution::new(width, height))
+                }
+                (None, Some(fps)) => RequestedFormatType::HighestFrameRate(fps),
+                (None, None) => RequestedFormatType::AbsoluteHighestFrameRate,
+            };
+        let requested =
+            nokhwa::utils::RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(format_type);
+        let mut camera = nokhwa::Camera::new(nokhwa::utils::CameraIndex::Index(index), requested)
+            .with_context(|| format!("Couldn't open camera {}", index))?;
         camera
             .open_stream()
-            .context("Couldn't start camera stream")
-            .unwrap();
-        Self { camera }
+            .context("Couldn't start camera stream")?;
+        Ok(Self { camera })
     }
 }
 
@@ -307,8 +2777,25 @@ impl Iterator for CameraIter {
     fn next(&mut self) -> Option<Self::Item> {
         self.camera
             .frame()
-            .expect("Failed to get next frame from the camera")
+            .ok()?
             .decode_image::<nokhwa::pixel_format::RgbFormat>()
             .ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unusable_terminal_sizes_fall_back() {
+        assert_eq!(usable_terminal_size(Ok((0, 0))), FALLBACK_TERMINAL_SIZE);
+        assert_eq!(usable_terminal_size(Ok((120, 0))), FALLBACK_TERMINAL_SIZE);
+        let no_terminal = std::io::Error::other("not a terminal");
+        assert_eq!(
+            usable_terminal_size(Err(no_terminal)),
+            FALLBACK_TERMINAL_SIZE
+        );
+        assert_eq!(usable_terminal_size(Ok((120, 40))), (120, 40));
+    }
+}